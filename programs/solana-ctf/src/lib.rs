@@ -1,10 +1,25 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program,
+    hash::hash,
+    instruction::Instruction,
+    sysvar::{
+        self,
+        instructions::{load_current_index_checked, load_instruction_at_checked},
+    },
+};
+use anchor_lang::{system_program, Discriminator};
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{self, Mint as OldMint, Token as OldToken, TokenAccount as OldTokenAccount},
-    token_interface::TokenAccount,
+    token_interface::{
+        self, CloseAccount, Mint, SetAuthority, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+use spl_token_2022::extension::{
+    transfer_fee::{TransferFee, TransferFeeConfig},
+    BaseStateWithExtensions, StateWithExtensions,
 };
-use spl_token::instruction::AuthorityType;
+use spl_token_2022::instruction::AuthorityType;
 use std::slice::Iter;
 
 pub const OWNER: Pubkey = Pubkey::new_from_array([
@@ -18,66 +33,568 @@ declare_id!("EEvREcEYzAV31rNmw2QGJHQw54Gbc39vov2fbfCFf7PF");
 pub mod solana_ctf {
     use super::*;
 
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(ConfigError::Unauthorized));
+        }
+
+        if params.withdrawal_delay < 0 {
+            return Err(ConfigError::InvalidWithdrawalDelay.into());
+        }
+
+        let config = &mut ctx.accounts.config;
+        config.version = Config::VERSION;
+        config.usdc_mint = ctx.accounts.usdc_mint.key();
+        config.treasury = ctx.accounts.treasury.key();
+        config.arka_usdc_wallet = ctx.accounts.arka_usdc_wallet.key();
+        config.withdrawal_delay = params.withdrawal_delay;
+        config.fee_authority = params.fee_authority;
+        config.position_limits = params.position_limits;
+        config.max_promo_per_user = params.max_promo_per_user;
+        config.collateral_mints = params.collateral_mints;
+
+        msg!(
+            "Config initialized with usdc_mint={:?}, treasury={:?}, arka_usdc_wallet={:?}, withdrawal_delay={:?}, fee_authority={:?}, position_limits={:?}, max_promo_per_user={:?}, collateral_mints={:?}",
+            config.usdc_mint,
+            config.treasury,
+            config.arka_usdc_wallet,
+            config.withdrawal_delay,
+            config.fee_authority,
+            config.position_limits,
+            config.max_promo_per_user,
+            config.collateral_mints,
+        );
+
+        Ok(())
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(ConfigError::Unauthorized));
+        }
+
+        if params.withdrawal_delay < 0 {
+            return Err(ConfigError::InvalidWithdrawalDelay.into());
+        }
+
+        let config = &mut ctx.accounts.config;
+        config.treasury = ctx.accounts.treasury.key();
+        config.arka_usdc_wallet = ctx.accounts.arka_usdc_wallet.key();
+        config.withdrawal_delay = params.withdrawal_delay;
+        config.fee_authority = params.fee_authority;
+        config.position_limits = params.position_limits;
+        config.max_promo_per_user = params.max_promo_per_user;
+        config.collateral_mints = params.collateral_mints;
+
+        msg!(
+            "Config updated with treasury={:?}, arka_usdc_wallet={:?}, withdrawal_delay={:?}, fee_authority={:?}, position_limits={:?}, max_promo_per_user={:?}, collateral_mints={:?}",
+            config.treasury,
+            config.arka_usdc_wallet,
+            config.withdrawal_delay,
+            config.fee_authority,
+            config.position_limits,
+            config.max_promo_per_user,
+            config.collateral_mints,
+        );
+
+        Ok(())
+    }
+
+    pub fn initialize_fee_vault(ctx: Context<InitializeFeeVault>) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(FeeVaultError::Unauthorized));
+        }
+
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        fee_ledger.version = FeeLedger::VERSION;
+        fee_ledger.mint = ctx.accounts.mint.key();
+        fee_ledger.treasury = ctx.accounts.treasury.key();
+
+        msg!(
+            "Fee vault for mint={:?} created at {:?} with ledger {:?}, treasury={:?}",
+            fee_ledger.mint,
+            ctx.accounts.fee_vault.key(),
+            fee_ledger.key(),
+            fee_ledger.treasury,
+        );
+
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, params: WithdrawFeesParams) -> Result<()> {
+        // Only the treasury role can move collected fees out of the vault
+        if ctx.accounts.fee_authority.key() != ctx.accounts.config.fee_authority {
+            return Err(error!(FeeVaultError::Unauthorized));
+        }
+
+        if params.amount == 0 || params.amount > ctx.accounts.fee_vault.amount {
+            return Err(FeeVaultError::InsufficientFees.into());
+        }
+
+        let bump = ctx.bumps.delegate.to_be_bytes();
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[b"fees".as_ref(), mint_key.as_ref(), bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            mint: ctx.accounts.mint.to_account_info(),
+            from: ctx.accounts.fee_vault.to_account_info(),
+            to: ctx.accounts.treasury.to_account_info(),
+            authority: ctx.accounts.delegate.to_account_info(),
+        };
+
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+
+        token_interface::transfer_checked(cpi_context, params.amount, ctx.accounts.mint.decimals)?;
+
+        let fee_ledger = &mut ctx.accounts.fee_ledger;
+        fee_ledger.withdrawn = fee_ledger
+            .withdrawn
+            .checked_add(params.amount)
+            .ok_or(FeeVaultError::LedgerOverflow)?;
+
+        emit!(FeesWithdrawn {
+            amount: params.amount,
+            treasury: ctx.accounts.treasury.key(),
+            total_withdrawn: fee_ledger.withdrawn,
+        });
+
+        msg!(
+            "Withdrew {:?} fees to treasury={:?}, total withdrawn={:?}",
+            params.amount,
+            ctx.accounts.treasury.key(),
+            fee_ledger.withdrawn,
+        );
+
+        Ok(())
+    }
+
+    pub fn set_fee_config(ctx: Context<SetFeeConfig>, params: FeeConfigParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(FeeConfigError::Unauthorized));
+        }
+
+        let fee_config = &mut ctx.accounts.fee_config;
+        fee_config.set(&params)?;
+
+        msg!(
+            "Default fee config set with tier_commission_bps={:?} maker_fee_bps={:?} taker_fee_bps={:?} referral_share_bps={:?}",
+            fee_config.tier_commission_bps,
+            fee_config.maker_fee_bps,
+            fee_config.taker_fee_bps,
+            fee_config.referral_share_bps,
+        );
+
+        Ok(())
+    }
+
+    pub fn set_event_fee_config(
+        ctx: Context<SetEventFeeConfig>,
+        params: EventFeeConfigParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(FeeConfigError::Unauthorized));
+        }
+
+        let fee_config = &mut ctx.accounts.event_fee_config;
+        fee_config.set(&params.fees)?;
+        ctx.accounts.event_data.has_fee_config = true;
+
+        msg!(
+            "Fee config for event_id={:?} set with tier_commission_bps={:?} maker_fee_bps={:?} taker_fee_bps={:?} referral_share_bps={:?}",
+            params.event_id,
+            fee_config.tier_commission_bps,
+            fee_config.maker_fee_bps,
+            fee_config.taker_fee_bps,
+            fee_config.referral_share_bps,
+        );
+
+        Ok(())
+    }
+
+    pub fn close_event_fee_config(
+        ctx: Context<CloseEventFeeConfig>,
+        params: CloseEventFeeConfigParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(FeeConfigError::Unauthorized));
+        }
+
+        ctx.accounts.event_data.has_fee_config = false;
+
+        msg!(
+            "Closed fee config for event_id={:?}, falling back to the default",
+            params.event_id
+        );
+
+        Ok(())
+    }
+
+    pub fn set_event_position_limits(
+        ctx: Context<SetEventPositionLimits>,
+        params: EventPositionLimitsParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PositionLimitsError::Unauthorized));
+        }
+
+        ctx.accounts.event_position_limits.version = EventPositionLimits::VERSION;
+        ctx.accounts.event_position_limits.limits = params.limits;
+        ctx.accounts.event_data.has_position_limits = true;
+
+        msg!(
+            "Position limits for event_id={:?} set to {:?}",
+            params.event_id,
+            params.limits,
+        );
+
+        Ok(())
+    }
+
+    pub fn close_event_position_limits(
+        ctx: Context<CloseEventPositionLimits>,
+        params: CloseEventPositionLimitsParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PositionLimitsError::Unauthorized));
+        }
+
+        ctx.accounts.event_data.has_position_limits = false;
+
+        msg!(
+            "Closed position limits for event_id={:?}, only the global limits apply",
+            params.event_id
+        );
+
+        Ok(())
+    }
+
+    pub fn update_user_profile(
+        ctx: Context<UpdateUserProfile>,
+        params: UpdateUserProfileParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(UpdateUserProfileError::Unauthorized));
+        }
+
+        let user_profile = &mut ctx.accounts.user_profile;
+
+        // Moving the funds' destination or dropping the intent requirement needs the
+        // consent of the wallet currently bound, either as a signer or through an intent
+        let rebinds_wallet =
+            user_profile.wallet != Pubkey::default() && params.wallet != user_profile.wallet;
+        let drops_intent = user_profile.requires_intent()
+            && params.status & UserProfile::STATUS_REQUIRES_INTENT == 0;
+        let wallet_signed = ctx
+            .accounts
+            .wallet
+            .as_ref()
+            .is_some_and(|wallet| wallet.key() == user_profile.wallet);
+        if (rebinds_wallet || drops_intent) && !wallet_signed && params.intent_nonce.is_none() {
+            return Err(UpdateUserProfileError::WalletConsentRequired.into());
+        }
+        if params.intent_nonce.is_some() {
+            verify_user_intent(
+                user_profile,
+                params.intent_nonce,
+                IntentAction::UpdateProfile {
+                    wallet: params.wallet,
+                    status: params.status,
+                },
+                ctx.accounts.instructions_sysvar.as_ref(),
+            )?;
+        }
+
+        // Users created before profiles existed get one on their first update
+        if user_profile.created_at == 0 {
+            user_profile.version = UserProfile::VERSION;
+            user_profile.user_id = params.user_id;
+            user_profile.created_at = Clock::get()?.unix_timestamp;
+        }
+
+        user_profile.wallet = params.wallet;
+        user_profile.kyc_tier = params.kyc_tier;
+        user_profile.status = params.status;
+        user_profile.referrer = params.referrer;
+
+        msg!(
+            "Updated profile for user_id={:?} wallet={:?} kyc_tier={:?} status={:?} referrer={:?}",
+            params.user_id,
+            params.wallet,
+            params.kyc_tier,
+            params.status,
+            params.referrer,
+        );
+
+        Ok(())
+    }
+
     pub fn buy_order(ctx: Context<BuyOrder>, params: BuyOrderParams) -> Result<()> {
         // Verify if the signer is the owner
         if ctx.accounts.owner.key() != OWNER {
             return Err(error!(BuyOrderError::Unauthorized));
         }
 
+        if ctx.accounts.order.kind != OrderKind::Buy {
+            return Err(BuyOrderError::OrderMismatch.into());
+        }
+
+        if ctx.accounts.user_profile.is_frozen() {
+            return Err(BuyOrderError::UserFrozen.into());
+        }
+
         // Validate that the price is between (0-1 dollar)
         let event_total_price = ctx.accounts.event_data.event_total_price;
         if params.order_price > event_total_price {
             return Err(BuyOrderError::InvalidPrice.into());
         }
 
+        // The event's own schedule takes precedence over the default one
+        let fee_config = ctx
+            .accounts
+            .event_data
+            .fee_config(
+                ctx.accounts
+                    .event_fee_config
+                    .as_deref()
+                    .map(|fee_config| &**fee_config),
+                &ctx.accounts.fee_config,
+            )
+            .ok_or(BuyOrderError::MissingEventFeeConfig)?;
+        let kyc_tier = ctx.accounts.user_profile.kyc_tier;
+        let referrer = ctx.accounts.user_profile.referrer;
+        // The rate in force now is what this lot pays when it is sold
+        let commission_bps = fee_config.commission_bps(kyc_tier);
+
+        // Both the global and the event's own position limits apply
+        let position = &ctx.accounts.user_arka_event_account;
+        let fill_notional = params
+            .order_price
+            .checked_mul(params.quantity)
+            .ok_or(BuyOrderError::AmountOverflow)?;
+        ctx.accounts.config.position_limits.check(
+            position,
+            params.order_type,
+            params.quantity,
+            fill_notional,
+        )?;
+        if let Some(event_limits) = &ctx.accounts.event_position_limits {
+            event_limits.limits.check(
+                position,
+                params.order_type,
+                params.quantity,
+                fill_notional,
+            )?;
+        } else if ctx.accounts.event_data.has_position_limits {
+            return Err(BuyOrderError::MissingEventPositionLimits.into());
+        }
+
+        // Every fill is taken from the funds locked for the order
+        let order = &mut ctx.accounts.order;
+        if !order.is_open() {
+            return Err(BuyOrderError::OrderNotOpen.into());
+        }
+
+        if order.event_id != params.event_id || order.order_type != params.order_type {
+            return Err(BuyOrderError::OrderMismatch.into());
+        }
+
+        if params.quantity == 0 || params.quantity > order.remaining_qty() {
+            return Err(BuyOrderError::FillExceedsOrder.into());
+        }
+
+        // Never fill a buy above the user's limit price
+        if params.order_price > order.price {
+            return Err(BuyOrderError::PriceWorseThanLimit.into());
+        }
+
         let bump = ctx.bumps.delegate.to_be_bytes();
         let user_id = params.user_id.to_le_bytes();
-        let seeds = &[b"usdc_uid_", user_id.as_ref(), bump.as_ref()];
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
+        ];
         let signer_seeds = [&seeds[..]];
 
         /* Debit the USDC from user account to Arka account */
-        let usdc_amount = params.order_price * params.quantity;
-        msg!("Total amount of USDC to deduct: {:?}", usdc_amount);
+        let usdc_amount = fill_notional;
+        let trading_fee = FeeConfig::apply_bps(
+            usdc_amount,
+            fee_config.trading_fee_bps(params.liquidity) as u64,
+        );
+        let referral_fee = fee_config.referral_fee(trading_fee, referrer);
+        msg!(
+            "Total amount of USDC to deduct: {:?}, trading fee: {:?}",
+            usdc_amount,
+            trading_fee,
+        );
+
+        // Token-2022 transfer fees on moves into the event escrow and the fee vault come out of
+        // the lock, so both receive exactly the notional and the fee
+        let mint = ctx.accounts.mint.to_account_info();
+        let escrow_amount = gross_amount(&mint, usdc_amount)?;
+        let vault_amount = gross_amount(&mint, trading_fee - referral_fee)?;
+        let debit = escrow_amount + vault_amount + referral_fee;
+
+        if debit > order.remaining_locked() {
+            return Err(BuyOrderError::InsufficientLockedFunds.into());
+        }
+
+        // Fills spend the order's promo first, fees paid with promo are not part of the position
+        let promo_before = order.filled_amount.min(order.promo_amount);
+        let locked_before = order.remaining_locked();
+        order.record_fill(params.quantity, debit);
+        ctx.accounts
+            .user_profile
+            .release_order(locked_before, order);
+        let promo_funded =
+            (order.filled_amount.min(order.promo_amount) - promo_before).min(usdc_amount);
+
+        msg!(
+            "Filled order_id={:?} qty={:?}/{:?}, status={:?}",
+            order.order_id,
+            order.filled_qty,
+            order.quantity,
+            order.status,
+        );
 
-        let cpi_accounts = token::Transfer {
+        let cpi_accounts = TransferChecked {
+            mint: ctx.accounts.mint.to_account_info(),
             from: ctx.accounts.user_usdc_token_account.to_account_info(),
             to: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
             authority: ctx.accounts.delegate.to_account_info(),
         };
 
         let cpi_context = CpiContext::new_with_signer(
-            ctx.accounts.old_token_program.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
             cpi_accounts,
             &signer_seeds,
         );
 
-        token::transfer(cpi_context, usdc_amount)?;
+        token_interface::transfer_checked(cpi_context, escrow_amount, ctx.accounts.mint.decimals)?;
 
-        /* Mint Arka token into user account */
-        let quantity = params.quantity;
-        let order_price = params.order_price;
-        let order_type = params.order_type.clone() as usize;
-        let user_event_account = &mut ctx.accounts.user_arka_event_account;
+        if vault_amount > 0 {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.user_usdc_token_account.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                vault_amount,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        if referral_fee > 0 {
+            let referrer_account = ctx
+                .accounts
+                .referrer_token_account
+                .as_ref()
+                .ok_or_else(|| error!(BuyOrderError::MissingReferrerAccount))?;
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.user_usdc_token_account.to_account_info(),
+                to: referrer_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
 
-        let current_quantity = user_event_account.total_qty;
-        let current_price = user_event_account.avg_purchase_price;
+            token_interface::transfer_checked(
+                cpi_context,
+                referral_fee,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        ctx.accounts
+            .fee_ledger
+            .record(trading_fee, 0, referral_fee)?;
+
+        emit!(FeeCharged {
+            user_id: params.user_id,
+            event_id: params.event_id,
+            order_id: params.order_id,
+            kind: OrderKind::Buy,
+            liquidity: params.liquidity,
+            notional: usdc_amount,
+            trading_fee,
+            commission: 0,
+            referrer,
+            referral_fee,
+        });
+
+        let user_profile = &mut ctx.accounts.user_profile;
+        user_profile.wagered_volume = user_profile
+            .wagered_volume
+            .checked_add(usdc_amount)
+            .ok_or(BuyOrderError::AmountOverflow)?;
 
-        let new_price = ((current_price[order_type] * current_quantity[order_type])
-            + (order_price * quantity))
-            / (current_quantity[order_type] + quantity);
+        /* Mint Arka token into user account */
+        let order_type = params.order_type as usize;
+        let user_event_account = &mut ctx.accounts.user_arka_event_account;
 
-        user_event_account.avg_purchase_price[order_type] = new_price;
-        user_event_account.total_qty[order_type] += quantity;
-        user_event_account.comission = params.commission;
+        user_event_account.version = UserEventData::VERSION;
+        let held_before = user_event_account.held_qty();
+        let current_quantity = user_event_account.total_qty[order_type];
+        let current_price = user_event_account.avg_purchase_price[order_type];
+
+        user_event_account.add_position(
+            params.order_type,
+            params.order_price,
+            params.quantity,
+            commission_bps,
+            trading_fee,
+            promo_funded,
+        )?;
+        ctx.accounts
+            .event_data
+            .add_outstanding(params.order_type, params.quantity);
 
         msg!(
-            "Previous avg_price={:?} qty={:?}, New avg_price={:?} qty={:?}",
-            current_price[order_type],
-            current_quantity[order_type],
-            new_price,
+            "Previous avg_price={:?} qty={:?}, New avg_price={:?} qty={:?} commission_bps={:?}",
+            current_price,
+            current_quantity,
+            user_event_account.avg_purchase_price[order_type],
             user_event_account.total_qty[order_type],
+            user_event_account.commission_bps[order_type],
         );
 
+        ctx.accounts
+            .user_profile
+            .track_position(held_before, &ctx.accounts.user_arka_event_account);
+
         Ok(())
     }
 
@@ -90,31 +607,71 @@ pub mod solana_ctf {
             return Err(error!(TranferFromUserWalletError::Unauthorized));
         }
 
-        let mut amount_from_usdc_wallet = data.amount;
-        let mut amount_from_promo_wallet = 0_u64;
+        if ctx.accounts.user_profile.is_frozen() {
+            return Err(TranferFromUserWalletError::UserFrozen.into());
+        }
 
-        if let Some(promo_account) = &ctx.accounts.promo_account {
-            let promo_balance = promo_account.amount;
+        // The lock must cover the order notional, anything above it pays trading fees
+        let notional = data
+            .price
+            .checked_mul(data.quantity)
+            .ok_or(TranferFromUserWalletError::InvalidOrderAmount)?;
+        if data.amount < notional {
+            return Err(TranferFromUserWalletError::InvalidOrderAmount.into());
+        }
 
-            amount_from_usdc_wallet = if data.amount > promo_balance {
-                data.amount - promo_balance
-            } else {
-                0_u64
-            };
+        verify_user_intent(
+            &mut ctx.accounts.user_profile,
+            data.intent_nonce,
+            IntentAction::BuyOrder {
+                order_id: data.order_id,
+                event_id: data.event_id,
+                order_type: data.order_type,
+                price: data.price,
+                quantity: data.quantity,
+                amount: data.amount,
+            },
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
 
-            amount_from_promo_wallet = if data.amount < promo_balance {
-                data.amount
-            } else {
-                promo_balance
-            };
-        } else {
-            msg!("Promo account was not passed to this contract!");
+        if matches!(data.promo_policy, Some(policy) if !policy.is_valid()) {
+            return Err(TranferFromUserWalletError::InvalidPromoPolicy.into());
         }
 
-        if amount_from_usdc_wallet > 0 {
-            if ctx.accounts.user_usdc_token_account.is_none() {
-                return Err(error!(TranferFromUserWalletError::InsufficientBalance));
+        let promo_balance = match &ctx.accounts.promo_account {
+            Some(promo_account) => promo_account.amount,
+            None => {
+                msg!("Promo account was not passed to this contract!");
+                0
             }
+        };
+        let usdc_balance = ctx
+            .accounts
+            .user_usdc_token_account
+            .as_ref()
+            .map_or(0, |usdc_account| usdc_account.amount);
+
+        // The order can only restrict the event's promo policy further
+        let (amount_from_usdc_wallet, amount_from_promo_wallet) = PromoPolicy::split(
+            ctx.accounts.event_data.promo_policy,
+            data.promo_policy,
+            data.amount,
+            promo_balance,
+            usdc_balance,
+        )
+        .ok_or(TranferFromUserWalletError::InsufficientBalance)?;
+
+        if amount_from_usdc_wallet > 0 && ctx.accounts.user_usdc_token_account.is_none() {
+            return Err(error!(TranferFromUserWalletError::InsufficientBalance));
+        }
+
+        // Token-2022 transfer fees are withheld on the way into the escrow, the order only
+        // records what arrived and that must still cover the notional
+        let mint = ctx.accounts.mint.to_account_info();
+        let usdc_locked = received_amount(&mint, amount_from_usdc_wallet)?;
+        let promo_locked = received_amount(&mint, amount_from_promo_wallet)?;
+        if usdc_locked + promo_locked < notional {
+            return Err(TranferFromUserWalletError::InvalidOrderAmount.into());
         }
 
         if amount_from_promo_wallet > 0 {
@@ -131,7 +688,8 @@ pub mod solana_ctf {
             ];
             let signer_seeds = [&promo_seeds[..]];
 
-            let cpi_accounts = token::Transfer {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
                 from: ctx
                     .accounts
                     .promo_account
@@ -153,7 +711,11 @@ pub mod solana_ctf {
                 &signer_seeds,
             );
 
-            token::transfer(cpi_context, amount_from_promo_wallet)?;
+            token_interface::transfer_checked(
+                cpi_context,
+                amount_from_promo_wallet,
+                ctx.accounts.mint.decimals,
+            )?;
         }
 
         if amount_from_usdc_wallet > 0 {
@@ -161,7 +723,8 @@ pub mod solana_ctf {
             let seeds = &[b"money", bump.as_ref()];
             let signer_seeds = [&seeds[..]];
 
-            let cpi_accounts = token::Transfer {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
                 from: ctx
                     .accounts
                     .user_usdc_token_account
@@ -178,7 +741,11 @@ pub mod solana_ctf {
                 &signer_seeds,
             );
 
-            token::transfer(cpi_context, amount_from_usdc_wallet)?;
+            token_interface::transfer_checked(
+                cpi_context,
+                amount_from_usdc_wallet,
+                ctx.accounts.mint.decimals,
+            )?;
         }
 
         msg!(
@@ -190,6 +757,29 @@ pub mod solana_ctf {
             amount_from_promo_wallet
         );
 
+        let order = &mut ctx.accounts.order;
+        order.version = Order::VERSION;
+        order.order_id = data.order_id;
+        order.user_id = data.user_id;
+        order.event_id = data.event_id;
+        order.mint = ctx.accounts.mint.key();
+        order.kind = OrderKind::Buy;
+        order.order_type = data.order_type;
+        order.price = data.price;
+        order.quantity = data.quantity;
+        order.filled_qty = 0;
+        order.usdc_amount = usdc_locked;
+        order.promo_amount = promo_locked;
+        order.filled_amount = 0;
+        order.refunded_amount = 0;
+        order.refunded_promo = 0;
+        order.status = OrderStatus::Open;
+        order.created_at = Clock::get()?.unix_timestamp;
+
+        if order.remaining_locked() > 0 {
+            ctx.accounts.user_profile.open_orders += 1;
+        }
+
         Ok(())
     }
 
@@ -202,6 +792,62 @@ pub mod solana_ctf {
             return Err(error!(TranferFromUserPdaError::Unauthorized));
         }
 
+        if !ctx.accounts.user_profile.can_withdraw() {
+            return Err(TranferFromUserPdaError::WithdrawalsBlocked.into());
+        }
+
+        verify_user_intent(
+            &mut ctx.accounts.user_profile,
+            data.intent_nonce,
+            IntentAction::Withdraw {
+                mint: ctx.accounts.mint.key(),
+                order_id: data.order_id,
+                utr_id: data.utr_id.clone(),
+                amount: data.amount,
+                promo_amount: data.promo_amount,
+            },
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+
+        // Releasing the funds of an order must restore each source exactly as it was locked
+        if let Some(order) = &ctx.accounts.order {
+            if !order.is_open() && order.remaining_locked() == 0 {
+                return Err(TranferFromUserPdaError::OrderNotOpen.into());
+            }
+
+            let (usdc_refund, promo_refund) = order
+                .refund_split(data.amount)
+                .ok_or(TranferFromUserPdaError::ExceedsLockedFunds)?;
+            if promo_refund != data.promo_amount {
+                return Err(TranferFromUserPdaError::MismatchedRefundSplit.into());
+            }
+            if (usdc_refund > 0 && ctx.accounts.user_usdc_token_account.is_none())
+                || (promo_refund > 0 && ctx.accounts.promo_account.is_none())
+            {
+                return Err(TranferFromUserPdaError::MissingRefundAccount.into());
+            }
+        } else {
+            // Without an order only cash no open order still holds can leave the escrow
+            if data.promo_amount > 0 {
+                return Err(TranferFromUserPdaError::PromoRequiresOrder.into());
+            }
+
+            let locked = ctx
+                .accounts
+                .user_profile
+                .locked_in_escrow(&ctx.accounts.mint.key(), ctx.remaining_accounts)?;
+            let free = ctx.accounts.escrow_account.amount.saturating_sub(locked);
+            if data.amount > free {
+                msg!(
+                    "Release amount={:?} exceeds escrow balance={:?} minus locked={:?}",
+                    data.amount,
+                    ctx.accounts.escrow_account.amount,
+                    locked,
+                );
+                return Err(TranferFromUserPdaError::ExceedsFreeFunds.into());
+            }
+        }
+
         msg!(
             "Releasing funds from user pda to user wallet for user_id={:?}, amount={:?} order_id={:?}, event_id={:?}, utr_id={:?}",
             data.user_id,
@@ -213,14 +859,27 @@ pub mod solana_ctf {
 
         let bump = ctx.bumps.delegate.to_be_bytes();
         let user_id = data.user_id.to_le_bytes();
-        let seeds = &[b"usdc_uid_", user_id.as_ref(), bump.as_ref()];
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
+        ];
         let signer_seeds = [&seeds[..]];
 
-        let usdc_account_amount = data.amount - data.promo_amount;
+        let usdc_account_amount = data
+            .amount
+            .checked_sub(data.promo_amount)
+            .ok_or(TranferFromUserPdaError::PromoExceedsAmount)?;
+        let mut released_usdc = 0;
+        let mut released_promo = 0;
 
         if let Some(usdc_account) = &ctx.accounts.user_usdc_token_account {
             if usdc_account_amount > 0 {
-                let cpi_accounts = token::Transfer {
+                let cpi_accounts = TransferChecked {
+                    mint: ctx.accounts.mint.to_account_info(),
                     to: usdc_account.to_account_info(),
                     from: ctx.accounts.escrow_account.to_account_info(),
                     authority: ctx.accounts.delegate.to_account_info(),
@@ -232,7 +891,12 @@ pub mod solana_ctf {
                     &signer_seeds,
                 );
 
-                token::transfer(cpi_context, usdc_account_amount)?;
+                token_interface::transfer_checked(
+                    cpi_context,
+                    usdc_account_amount,
+                    ctx.accounts.mint.decimals,
+                )?;
+                released_usdc = usdc_account_amount;
             }
         } else {
             msg!("Usdc account does not exists!");
@@ -240,7 +904,8 @@ pub mod solana_ctf {
 
         if let Some(promo_account) = &ctx.accounts.promo_account {
             if data.promo_amount > 0 {
-                let cpi_accounts = token::Transfer {
+                let cpi_accounts = TransferChecked {
+                    mint: ctx.accounts.mint.to_account_info(),
                     to: promo_account.to_account_info(),
                     from: ctx.accounts.escrow_account.to_account_info(),
                     authority: ctx.accounts.delegate.to_account_info(),
@@ -252,37 +917,340 @@ pub mod solana_ctf {
                     &signer_seeds,
                 );
 
-                token::transfer(cpi_context, data.promo_amount)?;
+                token_interface::transfer_checked(
+                    cpi_context,
+                    data.promo_amount,
+                    ctx.accounts.mint.decimals,
+                )?;
+                released_promo = data.promo_amount;
             }
         } else {
             msg!("Promo account was not passed to this contract!");
         }
 
+        let release_receipt = &mut ctx.accounts.release_receipt;
+        release_receipt.version = ReleaseReceipt::VERSION;
+        release_receipt.utr_hash = hash(data.utr_id.as_bytes()).to_bytes();
+        release_receipt.order_id = data.order_id;
+        release_receipt.user_id = data.user_id;
+        release_receipt.event_id = data.event_id;
+        release_receipt.usdc_amount = released_usdc;
+        release_receipt.promo_amount = released_promo;
+        release_receipt.created_at = Clock::get()?.unix_timestamp;
+
+        // Releasing the funds of an order refunds what is left of it
+        if let Some(order) = &mut ctx.accounts.order {
+            let locked_before = order.remaining_locked();
+            order.record_refund(released_usdc, released_promo);
+            ctx.accounts
+                .user_profile
+                .release_order(locked_before, order);
+        }
+
         Ok(())
     }
 
-    pub fn initialize_user_ata(
-        ctx: Context<InitializeUserAta>,
-        data: InitUserAtaParams,
-    ) -> Result<()> {
-        // Verify if the signer is the owner
-        if ctx.accounts.owner.key() != OWNER {
-            return Err(error!(InitializeUserAtaError::Unauthorized));
+    pub fn cancel_order(ctx: Context<CancelOrder>, params: CancelOrderParams) -> Result<()> {
+        // Either the owner or the user's registered wallet can cancel
+        let authority = ctx.accounts.authority.key();
+        if authority != OWNER && authority != ctx.accounts.user_profile.wallet {
+            return Err(error!(OrderError::Unauthorized));
         }
 
-        msg!("User ata created for user_id={:?}", data.user_id,);
+        // Orders filled below their locked amount can still be cancelled for the leftover
+        let order = &ctx.accounts.order;
+        if !order.is_open() && order.remaining_locked() == 0 {
+            return Err(OrderError::OrderNotOpen.into());
+        }
 
-        /* Create escrow account for storing order-init balance */
-        let bump = ctx.bumps.escrow_account.to_be_bytes();
-        let user_id_bytes = data.user_id.to_le_bytes();
-        let seeds = &[b"usdc_uid_", user_id_bytes.as_ref(), bump.as_ref()];
+        let (usdc_refund, promo_refund) = order.unfilled_split();
+
+        let bump = ctx.bumps.delegate.to_be_bytes();
+        let user_id = params.user_id.to_le_bytes();
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
+        ];
         let signer_seeds = [&seeds[..]];
 
-        token::set_authority(
-            CpiContext::new_with_signer(
+        if usdc_refund > 0 {
+            let usdc_account = ctx
+                .accounts
+                .user_usdc_token_account
+                .as_ref()
+                .ok_or_else(|| error!(OrderError::MissingRefundAccount))?;
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.escrow_account.to_account_info(),
+                to: usdc_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::SetAuthority {
-                    account_or_mint: ctx.accounts.escrow_account.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                usdc_refund,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        if promo_refund > 0 {
+            let promo_account = ctx
+                .accounts
+                .promo_account
+                .as_ref()
+                .ok_or_else(|| error!(OrderError::MissingRefundAccount))?;
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.escrow_account.to_account_info(),
+                to: promo_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                promo_refund,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        // Sell orders lock no funds, cancelling gives back the units they still promised
+        if ctx.accounts.order.kind == OrderKind::Sell {
+            let position = ctx
+                .accounts
+                .user_event_data
+                .as_mut()
+                .ok_or_else(|| error!(OrderError::MissingPosition))?;
+            position.release_committed(
+                ctx.accounts.order.order_type,
+                ctx.accounts.order.remaining_qty(),
+            );
+            let user_profile = &mut ctx.accounts.user_profile;
+            user_profile.open_sell_orders = user_profile.open_sell_orders.saturating_sub(1);
+        }
+
+        let locked_before = ctx.accounts.order.remaining_locked();
+        ctx.accounts.order.record_refund(usdc_refund, promo_refund);
+        ctx.accounts
+            .user_profile
+            .release_order(locked_before, &ctx.accounts.order);
+
+        msg!(
+            "Cancelled order_id={:?} for user_id={:?}, refunded amount={:?} promo_amount={:?}",
+            params.order_id,
+            params.user_id,
+            usdc_refund,
+            promo_refund,
+        );
+
+        Ok(())
+    }
+
+    pub fn close_order(ctx: Context<CloseOrder>, params: CloseOrderParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(OrderError::Unauthorized));
+        }
+
+        let order = &ctx.accounts.order;
+        if order.is_open() || order.remaining_locked() > 0 {
+            return Err(OrderError::OrderStillOpen.into());
+        }
+
+        msg!(
+            "Closing order_id={:?} status={:?}",
+            params.order_id,
+            ctx.accounts.order.status
+        );
+        Ok(())
+    }
+
+    pub fn close_release_receipt(
+        ctx: Context<CloseReleaseReceipt>,
+        params: CloseReleaseReceiptParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(ReleaseReceiptError::Unauthorized));
+        }
+
+        msg!(
+            "Closing release receipt for utr_id={:?} order_id={:?}",
+            params.utr_id,
+            ctx.accounts.release_receipt.order_id
+        );
+        Ok(())
+    }
+
+    pub fn request_withdrawal(
+        ctx: Context<RequestWithdrawal>,
+        params: WithdrawalParams,
+    ) -> Result<()> {
+        let withdrawal_request = &mut ctx.accounts.withdrawal_request;
+        withdrawal_request.version = WithdrawalRequest::VERSION;
+        withdrawal_request.user_id = params.user_id;
+        withdrawal_request.mint = params.mint;
+        withdrawal_request.requested_at = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Withdrawal requested for user_id={:?} mint={:?} at={:?}, executable after={:?}",
+            params.user_id,
+            params.mint,
+            withdrawal_request.requested_at,
+            withdrawal_request.requested_at + ctx.accounts.config.withdrawal_delay,
+        );
+
+        Ok(())
+    }
+
+    pub fn execute_withdrawal(
+        ctx: Context<ExecuteWithdrawal>,
+        params: WithdrawalParams,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let executable_at =
+            ctx.accounts.withdrawal_request.requested_at + ctx.accounts.config.withdrawal_delay;
+        if now < executable_at {
+            msg!(
+                "Withdrawal is locked until={:?}, now={:?}",
+                executable_at,
+                now
+            );
+            return Err(WithdrawalError::WithdrawalLocked.into());
+        }
+
+        // Funds locked for orders are not the user's to take, they cancel the orders first.
+        // Promo is not withdrawable at all, it is only ever spent on orders.
+        if ctx.accounts.user_profile.open_orders > 0 {
+            return Err(WithdrawalError::OrdersStillOpen.into());
+        }
+
+        let user_id_bytes = params.user_id.to_le_bytes();
+
+        let usdc_amount = ctx.accounts.escrow_account.amount;
+        if usdc_amount > 0 {
+            let bump = ctx.bumps.delegate.to_be_bytes();
+            let mint_key = ctx.accounts.mint.key();
+            let seeds = &[
+                b"escrow_uid_",
+                user_id_bytes.as_ref(),
+                b"_mint_",
+                mint_key.as_ref(),
+                bump.as_ref(),
+            ];
+            let signer_seeds = [&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.escrow_account.to_account_info(),
+                to: ctx.accounts.user_usdc_token_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                usdc_amount,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        msg!(
+            "Executed withdrawal for user_id={:?}, mint={:?}, amount={:?}",
+            params.user_id,
+            params.mint,
+            usdc_amount,
+        );
+
+        Ok(())
+    }
+
+    pub fn settle_withdrawal(
+        ctx: Context<SettleWithdrawal>,
+        params: WithdrawalParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(WithdrawalError::Unauthorized));
+        }
+
+        // The operator can only pre-empt the user by actually paying them out
+        if ctx.accounts.escrow_account.amount > 0 {
+            return Err(WithdrawalError::EscrowNotSettled.into());
+        }
+
+        msg!(
+            "Settled withdrawal request for user_id={:?} mint={:?}",
+            params.user_id,
+            params.mint,
+        );
+
+        Ok(())
+    }
+
+    pub fn initialize_user_ata(
+        ctx: Context<InitializeUserAta>,
+        data: InitUserAtaParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(InitializeUserAtaError::Unauthorized));
+        }
+
+        msg!(
+            "User ata created for user_id={:?} wallet={:?}",
+            data.user_id,
+            data.wallet,
+        );
+
+        let user_profile = &mut ctx.accounts.user_profile;
+        user_profile.version = UserProfile::VERSION;
+        user_profile.user_id = data.user_id;
+        user_profile.wallet = data.wallet;
+        user_profile.created_at = Clock::get()?.unix_timestamp;
+        user_profile.kyc_tier = data.kyc_tier;
+        user_profile.status = 0;
+
+        /* Create escrow account for storing order-init balance */
+        let bump = ctx.bumps.escrow_account.to_be_bytes();
+        let user_id_bytes = data.user_id.to_le_bytes();
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"escrow_uid_",
+            user_id_bytes.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    account_or_mint: ctx.accounts.escrow_account.to_account_info(),
                     current_authority: ctx.accounts.delegate.to_account_info(),
                 },
                 &signer_seeds,
@@ -300,10 +1268,10 @@ pub mod solana_ctf {
         ];
         let promo_signer_seeds = [&promo_seeds[..]];
 
-        token::set_authority(
+        token_interface::set_authority(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::SetAuthority {
+                SetAuthority {
                     account_or_mint: ctx.accounts.promo_account.to_account_info(),
                     current_authority: ctx.accounts.promo_delegate.to_account_info(),
                 },
@@ -317,7 +1285,64 @@ pub mod solana_ctf {
         let seeds = &[b"money", bump.as_ref()];
         let signer_seeds = [&seeds[..]];
 
-        let cpi_accounts = token::Transfer {
+        // The Arka wallet pays the transfer fee, so the user receives the full promo balance
+        let promo_amount = gross_amount(&ctx.accounts.mint.to_account_info(), data.promo_balance)?;
+
+        let cpi_accounts = TransferChecked {
+            mint: ctx.accounts.mint.to_account_info(),
+            from: ctx.accounts.arka_usdc_wallet.to_account_info(),
+            to: ctx.accounts.promo_account.to_account_info(),
+            authority: ctx.accounts.arka_delegate.to_account_info(),
+        };
+
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+
+        token_interface::transfer_checked(cpi_context, promo_amount, ctx.accounts.mint.decimals)?;
+
+        // Every promo credit is recorded against the campaign that funded it
+        if data.promo_balance > 0 {
+            let promo_grant = ctx
+                .accounts
+                .promo_grant
+                .as_mut()
+                .ok_or_else(|| error!(PromoGrantError::MissingPromoGrant))?;
+            ctx.accounts
+                .config
+                .check_promo_cap(user_profile, data.promo_balance)?;
+            promo_grant.grant(user_profile, &data.promo_campaign, data.promo_balance)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn grant_promo(ctx: Context<GrantPromo>, params: GrantPromoParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PromoGrantError::Unauthorized));
+        }
+
+        if params.amount == 0 {
+            return Err(PromoGrantError::InvalidAmount.into());
+        }
+
+        let user_profile = &mut ctx.accounts.user_profile;
+        ctx.accounts
+            .config
+            .check_promo_cap(user_profile, params.amount)?;
+
+        let bump = ctx.bumps.arka_delegate.to_be_bytes();
+        let seeds = &[b"money", bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        // The Arka wallet pays the transfer fee, so the user receives the full grant
+        let promo_amount = gross_amount(&ctx.accounts.mint.to_account_info(), params.amount)?;
+
+        let cpi_accounts = TransferChecked {
+            mint: ctx.accounts.mint.to_account_info(),
             from: ctx.accounts.arka_usdc_wallet.to_account_info(),
             to: ctx.accounts.promo_account.to_account_info(),
             authority: ctx.accounts.arka_delegate.to_account_info(),
@@ -329,7 +1354,114 @@ pub mod solana_ctf {
             &signer_seeds,
         );
 
-        token::transfer(cpi_context, data.promo_balance)?;
+        token_interface::transfer_checked(cpi_context, promo_amount, ctx.accounts.mint.decimals)?;
+
+        ctx.accounts
+            .promo_grant
+            .grant(user_profile, &params.promo_campaign, params.amount)
+    }
+
+    pub fn expire_promo(ctx: Context<ExpirePromo>, params: ExpirePromoParams) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PromoGrantError::Unauthorized));
+        }
+
+        let promo_grant = &ctx.accounts.promo_grant;
+        if Clock::get()?.unix_timestamp < promo_grant.expires_at {
+            return Err(PromoGrantError::PromoNotExpired.into());
+        }
+
+        // Promo the user has wagered through is theirs to keep
+        if promo_grant.wagering_met(ctx.accounts.user_profile.wagered_volume) {
+            return Err(PromoGrantError::WageringCompleted.into());
+        }
+
+        // Only this grant's share of the promo account expires, the rest belongs to other grants
+        let amount = promo_grant.unspent(
+            ctx.accounts.promo_account.amount,
+            ctx.accounts.user_profile.promo_outstanding,
+        );
+
+        if amount > 0 {
+            let bump = ctx.bumps.promo_delegate.to_be_bytes();
+            let user_id_bytes = params.user_id.to_le_bytes();
+            let seeds = &[b"promo_usdc_uid_", user_id_bytes.as_ref(), bump.as_ref()];
+            let signer_seeds = [&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.promo_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+                authority: ctx.accounts.promo_delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(cpi_context, amount, ctx.accounts.mint.decimals)?;
+        }
+
+        ctx.accounts.promo_grant.record_return(
+            &mut ctx.accounts.user_profile,
+            amount,
+            PromoReturnReason::Expired,
+        );
+
+        Ok(())
+    }
+
+    pub fn admin_clawback_promo(
+        ctx: Context<ClawbackPromo>,
+        params: ClawbackPromoParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PromoGrantError::Unauthorized));
+        }
+
+        let unspent = ctx.accounts.promo_grant.unspent(
+            ctx.accounts.promo_account.amount,
+            ctx.accounts.user_profile.promo_outstanding,
+        );
+        if params.amount > unspent {
+            return Err(PromoGrantError::ExceedsPromoGrant.into());
+        }
+
+        if params.amount > 0 {
+            let bump = ctx.bumps.promo_delegate.to_be_bytes();
+            let user_id_bytes = params.user_id.to_le_bytes();
+            let seeds = &[b"promo_usdc_uid_", user_id_bytes.as_ref(), bump.as_ref()];
+            let signer_seeds = [&seeds[..]];
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.promo_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+                authority: ctx.accounts.promo_delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                params.amount,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        ctx.accounts.promo_grant.record_return(
+            &mut ctx.accounts.user_profile,
+            params.amount,
+            PromoReturnReason::Clawback,
+        );
 
         Ok(())
     }
@@ -340,15 +1472,33 @@ pub mod solana_ctf {
             return Err(error!(InitializeEventError::Unauthorized));
         }
 
+        if !data.promo_policy.is_valid() {
+            return Err(InitializeEventError::InvalidPromoPolicy.into());
+        }
+
+        // Promo accounts only hold USDC, events in other mints can't be paid with promo
+        if ctx.accounts.mint.key() != ctx.accounts.config.usdc_mint
+            && data.promo_policy != PromoPolicy::Disallowed
+        {
+            return Err(InitializeEventError::PromoRequiresUsdc.into());
+        }
+
+        ctx.accounts.event_data.version = EventData::VERSION;
         ctx.accounts.event_data.event_id = data.event_id;
         ctx.accounts.event_data.outcome = EventOutcome::Null;
         ctx.accounts.event_data.is_outcome_set = false;
         ctx.accounts.event_data.event_total_price = data.event_total_price;
+        ctx.accounts.event_data.promo_policy = data.promo_policy;
+        ctx.accounts.event_data.collateral_mint = ctx.accounts.mint.key();
+        ctx.accounts.event_data.outstanding_qty = [0; 2];
+        ctx.accounts.event_data.has_fee_config = false;
+        ctx.accounts.event_data.has_position_limits = false;
 
         msg!(
-            "Event created on chain with event_id={:?}, event_price={:?}",
+            "Event created on chain with event_id={:?}, event_price={:?}, collateral_mint={:?}",
             data.event_id,
             data.event_total_price,
+            ctx.accounts.mint.key(),
         );
 
         let bump = ctx.bumps.escrow_account.to_be_bytes();
@@ -356,10 +1506,10 @@ pub mod solana_ctf {
         let seeds = &[b"usdc_eid_", event_id_bytes.as_ref(), bump.as_ref()];
         let signer_seeds = [&seeds[..]];
 
-        token::set_authority(
+        token_interface::set_authority(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                token::SetAuthority {
+                SetAuthority {
                     account_or_mint: ctx.accounts.escrow_account.to_account_info(),
                     current_authority: ctx.accounts.delegate.to_account_info(),
                 },
@@ -372,6 +1522,37 @@ pub mod solana_ctf {
         Ok(())
     }
 
+    pub fn set_event_promo_policy(
+        ctx: Context<SetEventPromoPolicy>,
+        params: EventPromoPolicyParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(SetEventPromoPolicyError::Unauthorized));
+        }
+
+        if !params.promo_policy.is_valid() {
+            return Err(SetEventPromoPolicyError::InvalidPromoPolicy.into());
+        }
+
+        // Promo accounts only hold USDC, events in other mints can't be paid with promo
+        if ctx.accounts.event_data.collateral_mint != ctx.accounts.config.usdc_mint
+            && params.promo_policy != PromoPolicy::Disallowed
+        {
+            return Err(SetEventPromoPolicyError::PromoRequiresUsdc.into());
+        }
+
+        ctx.accounts.event_data.promo_policy = params.promo_policy;
+
+        msg!(
+            "Promo policy for event_id={:?} set to {:?}",
+            params.event_id,
+            params.promo_policy,
+        );
+
+        Ok(())
+    }
+
     pub fn update_outcome(ctx: Context<UpdateOutcome>, data: EventOutcome) -> Result<()> {
         // Verify if the signer is the owner
         if ctx.accounts.owner.key() != OWNER {
@@ -407,6 +1588,15 @@ pub mod solana_ctf {
             return Err(error!(CloseEventAccountError::Unauthorized));
         }
 
+        // A closed escrow has been swept by close_event_escrow
+        let escrow_info = ctx.accounts.escrow_account.to_account_info();
+        let escrow_amount = if escrow_info.data_is_empty() {
+            0
+        } else {
+            TokenAccount::try_deserialize(&mut &escrow_info.try_borrow_data()?[..])?.amount
+        };
+        ctx.accounts.event_data.check_closable(escrow_amount)?;
+
         msg!("Closing event account with event_id={:?}", params.event_id);
         Ok(())
     }
@@ -422,7 +1612,9 @@ pub mod solana_ctf {
 
         let user_account = &ctx.accounts.user_event_data;
         for order_type in solana_ctf::OrderType::iterator() {
-            let qty = user_account.total_qty[order_type.clone() as usize];
+            // Open sell orders are cancelled first, they release their units on cancel
+            let side = *order_type as usize;
+            let qty = user_account.total_qty[side].max(user_account.committed_qty[side]);
             if qty > 0 {
                 msg!("Pending qty={:?} order_type={:?}", qty, order_type);
                 return Err(CloseUserEventError::PendingQuantity.into());
@@ -436,33 +1628,314 @@ pub mod solana_ctf {
         Ok(())
     }
 
-    pub fn initialize_promo_account(
-        ctx: Context<InitializePromoAccount>,
-        data: InitPromoAccount,
+    pub fn close_user_accounts(
+        ctx: Context<CloseUserAccounts>,
+        params: CloseUserAccountsParams,
     ) -> Result<()> {
         // Verify if the signer is the owner
         if ctx.accounts.owner.key() != OWNER {
-            return Err(error!(InitializeUserAtaError::Unauthorized));
+            return Err(error!(CloseUserAccountsError::Unauthorized));
         }
 
-        msg!("User promo ata created for user_id={:?}", data.user_id,);
+        // Funds of open orders are still locked in the escrow
+        if ctx.accounts.user_profile.open_orders > 0 {
+            return Err(CloseUserAccountsError::OrdersStillOpen.into());
+        }
 
-        let user_id_bytes = data.user_id.to_le_bytes();
-        /* Create promo account to store promo balance */
-        let promo_bump = ctx.bumps.promo_account.to_be_bytes();
-        let promo_seeds = &[
-            b"promo_usdc_uid_",
+        // Units still held or offered would be settled into the escrow being closed
+        if ctx.accounts.user_profile.open_positions > 0 {
+            return Err(CloseUserAccountsError::PositionsStillOpen.into());
+        }
+        if ctx.accounts.user_profile.open_sell_orders > 0 {
+            return Err(CloseUserAccountsError::SellOrdersStillOpen.into());
+        }
+
+        // A pending withdrawal would be executed against the escrow being closed
+        if !ctx.accounts.withdrawal_request.data_is_empty() {
+            return Err(CloseUserAccountsError::WithdrawalPending.into());
+        }
+
+        let user_id_bytes = params.user_id.to_le_bytes();
+        let mint_key = ctx.accounts.mint.key();
+        let decimals = ctx.accounts.mint.decimals;
+
+        // Any dust left in the escrow belongs to the user
+        let escrow_amount = ctx.accounts.escrow_account.amount;
+        let bump = ctx.bumps.escrow_account.to_be_bytes();
+        let seeds = &[
+            b"escrow_uid_",
             user_id_bytes.as_ref(),
-            promo_bump.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
         ];
-        let promo_signer_seeds = [&promo_seeds[..]];
+        let signer_seeds = [&seeds[..]];
 
-        token::set_authority(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                token::SetAuthority {
-                    account_or_mint: ctx.accounts.promo_account.to_account_info(),
-                    current_authority: ctx.accounts.promo_delegate.to_account_info(),
+        if escrow_amount > 0 {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.escrow_account.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.escrow_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+            token_interface::transfer_checked(cpi_context, escrow_amount, decimals)?;
+        }
+
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.escrow_account.to_account_info(),
+                destination: ctx.accounts.payer.to_account_info(),
+                authority: ctx.accounts.escrow_account.to_account_info(),
+            },
+            &signer_seeds,
+        ))?;
+
+        // Promo dust was never the user's cash, it goes back to the treasury
+        let mut promo_amount = 0;
+        if let Some(promo_account) = &ctx.accounts.promo_account {
+            // Each outstanding grant expires the way expire_promo would expire it, the grants
+            // are passed as remaining accounts
+            let now = Clock::get()?.unix_timestamp;
+            let user_profile = &mut ctx.accounts.user_profile;
+            let promo_outstanding = user_profile.promo_outstanding;
+            let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+            for info in ctx.remaining_accounts {
+                if *info.owner != crate::ID || !info.is_writable || seen.contains(info.key) {
+                    return Err(CloseUserAccountsError::MissingPromoGrants.into());
+                }
+                let mut promo_grant =
+                    PromoGrant::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+                if promo_grant.user_id != params.user_id || promo_grant.remaining() == 0 {
+                    return Err(CloseUserAccountsError::MissingPromoGrants.into());
+                }
+                // Promo of grants that haven't expired yet is still the user's to spend
+                if now < promo_grant.expires_at {
+                    return Err(CloseUserAccountsError::PromoNotExpired.into());
+                }
+                if promo_grant.wagering_met(user_profile.wagered_volume) {
+                    return Err(CloseUserAccountsError::WageringCompleted.into());
+                }
+
+                let amount = promo_grant.unspent(promo_account.amount, promo_outstanding);
+                promo_grant.record_return(user_profile, amount, PromoReturnReason::Expired);
+                promo_grant.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+                seen.push(*info.key);
+            }
+            if user_profile.promo_outstanding > 0 {
+                msg!(
+                    "Passed {:?} promo grants, promo_outstanding={:?} is left",
+                    seen.len(),
+                    user_profile.promo_outstanding
+                );
+                return Err(CloseUserAccountsError::MissingPromoGrants.into());
+            }
+
+            let treasury = ctx
+                .accounts
+                .treasury
+                .as_ref()
+                .ok_or(CloseUserAccountsError::MissingTreasury)?;
+            promo_amount = promo_account.amount;
+            let promo_bump = ctx
+                .bumps
+                .promo_account
+                .expect("Failed to get promo account")
+                .to_be_bytes();
+            let promo_seeds = &[
+                b"promo_usdc_uid_",
+                user_id_bytes.as_ref(),
+                promo_bump.as_ref(),
+            ];
+            let promo_signer_seeds = [&promo_seeds[..]];
+
+            if promo_amount > 0 {
+                let cpi_accounts = TransferChecked {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    from: promo_account.to_account_info(),
+                    to: treasury.to_account_info(),
+                    authority: promo_account.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_context =
+                    CpiContext::new_with_signer(cpi_program, cpi_accounts, &promo_signer_seeds);
+                token_interface::transfer_checked(cpi_context, promo_amount, decimals)?;
+            }
+
+            token_interface::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: promo_account.to_account_info(),
+                    destination: ctx.accounts.payer.to_account_info(),
+                    authority: promo_account.to_account_info(),
+                },
+                &promo_signer_seeds,
+            ))?;
+        }
+
+        msg!(
+            "Closed user accounts for user_id={:?} mint={:?} escrow_dust={:?} promo_dust={:?}",
+            params.user_id,
+            mint_key,
+            escrow_amount,
+            promo_amount,
+        );
+
+        Ok(())
+    }
+
+    pub fn close_event_escrow(
+        ctx: Context<CloseEventEscrow>,
+        params: CloseEventAccountParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(CloseEventEscrowError::Unauthorized));
+        }
+
+        // Positions can only be settled against the escrow until the event is resolved
+        if !ctx.accounts.event_data.is_outcome_set {
+            return Err(CloseEventEscrowError::EventNotResolved.into());
+        }
+        if ctx.accounts.event_data.outstanding_qty != [0; 2] {
+            msg!(
+                "Outstanding qty={:?}",
+                ctx.accounts.event_data.outstanding_qty
+            );
+            return Err(CloseEventEscrowError::OutstandingPositions.into());
+        }
+
+        let amount = ctx.accounts.escrow_account.amount;
+        let event_id_bytes = params.event_id.to_le_bytes();
+        let bump = ctx.bumps.escrow_account.to_be_bytes();
+        let seeds = &[b"usdc_eid_", event_id_bytes.as_ref(), bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        if amount > 0 {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.escrow_account.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+                authority: ctx.accounts.escrow_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, ctx.accounts.mint.decimals)?;
+        }
+
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.escrow_account.to_account_info(),
+                destination: ctx.accounts.payer.to_account_info(),
+                authority: ctx.accounts.escrow_account.to_account_info(),
+            },
+            &signer_seeds,
+        ))?;
+
+        msg!(
+            "Closed escrow for event_id={:?}, swept {:?} to treasury={:?}",
+            params.event_id,
+            amount,
+            ctx.accounts.treasury.key(),
+        );
+
+        Ok(())
+    }
+
+    pub fn migrate_event_data(
+        ctx: Context<MigrateEventData>,
+        params: MigrateEventDataParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(MigrateEventDataError::Unauthorized));
+        }
+
+        let account = ctx.accounts.event_data.to_account_info();
+        let migrated = EventData::migrate(
+            &account.try_borrow_data()?,
+            ctx.accounts.config.usdc_mint,
+            params.outstanding_qty,
+        )?;
+        write_migrated(
+            &account,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + EventData::LEN,
+            &migrated,
+        )?;
+
+        msg!(
+            "Migrated event_id={:?} to version={:?} collateral_mint={:?} outstanding_qty={:?}",
+            params.event_id,
+            migrated.version,
+            migrated.collateral_mint,
+            migrated.outstanding_qty,
+        );
+
+        Ok(())
+    }
+
+    pub fn migrate_user_event_data(
+        ctx: Context<MigrateUserEventData>,
+        params: MigrateUserEventDataParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(MigrateUserEventDataError::Unauthorized));
+        }
+
+        let account = ctx.accounts.user_event_data.to_account_info();
+        let migrated = UserEventData::migrate(&account.try_borrow_data()?)?;
+        write_migrated(
+            &account,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            8 + UserEventData::LEN,
+            &migrated,
+        )?;
+
+        msg!(
+            "Migrated user_id={:?} event_id={:?} qty={:?} cost_basis={:?}",
+            params.user_id,
+            params.event_id,
+            migrated.total_qty,
+            migrated.cost_basis,
+        );
+
+        Ok(())
+    }
+
+    pub fn initialize_promo_account(
+        ctx: Context<InitializePromoAccount>,
+        data: InitPromoAccount,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(InitializeUserAtaError::Unauthorized));
+        }
+
+        msg!("User promo ata created for user_id={:?}", data.user_id,);
+
+        let user_id_bytes = data.user_id.to_le_bytes();
+        /* Create promo account to store promo balance */
+        let promo_bump = ctx.bumps.promo_account.to_be_bytes();
+        let promo_seeds = &[
+            b"promo_usdc_uid_",
+            user_id_bytes.as_ref(),
+            promo_bump.as_ref(),
+        ];
+        let promo_signer_seeds = [&promo_seeds[..]];
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    account_or_mint: ctx.accounts.promo_account.to_account_info(),
+                    current_authority: ctx.accounts.promo_delegate.to_account_info(),
                 },
                 &promo_signer_seeds,
             ),
@@ -473,6 +1946,173 @@ pub mod solana_ctf {
         Ok(())
     }
 
+    pub fn initialize_user_escrow(
+        ctx: Context<InitializeUserEscrow>,
+        data: InitUserEscrowParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(InitializeUserAtaError::Unauthorized));
+        }
+
+        msg!(
+            "User escrow created for user_id={:?} mint={:?}",
+            data.user_id,
+            ctx.accounts.mint.key(),
+        );
+
+        let bump = ctx.bumps.escrow_account.to_be_bytes();
+        let user_id_bytes = data.user_id.to_le_bytes();
+        let mint_key = ctx.accounts.mint.key();
+        let seeds = &[
+            b"escrow_uid_",
+            user_id_bytes.as_ref(),
+            b"_mint_",
+            mint_key.as_ref(),
+            bump.as_ref(),
+        ];
+        let signer_seeds = [&seeds[..]];
+
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                SetAuthority {
+                    account_or_mint: ctx.accounts.escrow_account.to_account_info(),
+                    current_authority: ctx.accounts.delegate.to_account_info(),
+                },
+                &signer_seeds,
+            ),
+            AuthorityType::AccountOwner,
+            Some(ctx.accounts.delegate.key()), // Set the PDA as the new owner
+        )?;
+
+        Ok(())
+    }
+
+    pub fn migrate_user_escrow(
+        ctx: Context<MigrateUserEscrow>,
+        data: InitUserEscrowParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(MigrateUserEscrowError::Unauthorized));
+        }
+
+        let amount = ctx.accounts.legacy_escrow.amount;
+        let user_id_bytes = data.user_id.to_le_bytes();
+        let bump = ctx.bumps.legacy_escrow.to_be_bytes();
+        let seeds = &[b"usdc_uid_", user_id_bytes.as_ref(), bump.as_ref()];
+        let signer_seeds = [&seeds[..]];
+
+        // The legacy escrow is owned by itself, so it signs both the sweep and the close
+        if amount > 0 {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                from: ctx.accounts.legacy_escrow.to_account_info(),
+                to: ctx.accounts.escrow_account.to_account_info(),
+                authority: ctx.accounts.legacy_escrow.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+            token_interface::transfer_checked(cpi_context, amount, ctx.accounts.mint.decimals)?;
+        }
+
+        token_interface::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.legacy_escrow.to_account_info(),
+                destination: ctx.accounts.payer.to_account_info(),
+                authority: ctx.accounts.legacy_escrow.to_account_info(),
+            },
+            &signer_seeds,
+        ))?;
+
+        msg!(
+            "Migrated legacy escrow for user_id={:?} amount={:?} mint={:?}",
+            data.user_id,
+            amount,
+            ctx.accounts.mint.key(),
+        );
+
+        Ok(())
+    }
+
+    pub fn place_sell_order(
+        ctx: Context<PlaceSellOrder>,
+        params: PlaceSellOrderParams,
+    ) -> Result<()> {
+        // Verify if the signer is the owner
+        if ctx.accounts.owner.key() != OWNER {
+            return Err(error!(PlaceSellOrderError::Unauthorized));
+        }
+
+        if ctx.accounts.user_profile.is_frozen() {
+            return Err(PlaceSellOrderError::UserFrozen.into());
+        }
+
+        let event_total_price = ctx.accounts.event_data.event_total_price;
+        if params.price > event_total_price {
+            return Err(PlaceSellOrderError::InvalidPrice.into());
+        }
+
+        // Units already promised to other open sell orders can't be offered again
+        let free_qty = ctx
+            .accounts
+            .user_arka_event_account
+            .free_qty(params.order_type);
+        if params.quantity == 0 || params.quantity > free_qty {
+            return Err(PlaceSellOrderError::InsufficientPosition.into());
+        }
+
+        verify_user_intent(
+            &mut ctx.accounts.user_profile,
+            params.intent_nonce,
+            IntentAction::SellOrder {
+                order_id: params.order_id,
+                event_id: params.event_id,
+                order_type: params.order_type,
+                price: params.price,
+                quantity: params.quantity,
+            },
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+
+        ctx.accounts.user_arka_event_account.committed_qty[params.order_type as usize] +=
+            params.quantity;
+        ctx.accounts.user_profile.open_sell_orders += 1;
+
+        let order = &mut ctx.accounts.order;
+        order.version = Order::VERSION;
+        order.order_id = params.order_id;
+        order.user_id = params.user_id;
+        order.event_id = params.event_id;
+        order.mint = ctx.accounts.event_data.collateral_mint;
+        order.kind = OrderKind::Sell;
+        order.order_type = params.order_type;
+        order.price = params.price;
+        order.quantity = params.quantity;
+        order.filled_qty = 0;
+        order.usdc_amount = 0;
+        order.promo_amount = 0;
+        order.filled_amount = 0;
+        order.refunded_amount = 0;
+        order.refunded_promo = 0;
+        order.status = OrderStatus::Open;
+        order.created_at = Clock::get()?.unix_timestamp;
+
+        msg!(
+            "Sell order placed order_id={:?} user_id={:?} event_id={:?} order_type={:?} min_price={:?} qty={:?}",
+            params.order_id,
+            params.user_id,
+            params.event_id,
+            params.order_type,
+            params.price,
+            params.quantity,
+        );
+
+        Ok(())
+    }
+
     pub fn sell_order(ctx: Context<SellOrder>, params: SellOrderParams) -> Result<()> {
         // Verify if the signer is the owner
         if ctx.accounts.owner.key() != OWNER {
@@ -486,15 +2126,8 @@ pub mod solana_ctf {
             return Err(SellOrderError::InvalidTokenPrice.into());
         }
 
-        if params.selling_price == event_total_price {
-            if !ctx.accounts.event_data.is_outcome_set {
-                return Err(SellOrderError::EventNotFinished.into());
-            }
-
-            let outcome = ctx.accounts.event_data.outcome.clone() as u8 - 1;
-            if outcome != params.order_type as u8 {
-                return Err(SellOrderError::EventOutcomeMismatch.into());
-            }
+        if params.selling_price == event_total_price && !ctx.accounts.event_data.is_outcome_set {
+            return Err(SellOrderError::EventNotFinished.into());
         }
 
         /* Credit the USDC from user account to Arka account */
@@ -502,36 +2135,137 @@ pub mod solana_ctf {
             ctx.accounts.user_arka_event_account.avg_purchase_price[order_type];
         let total_qty = ctx.accounts.user_arka_event_account.total_qty[order_type];
 
-        if ctx.accounts.event_data.is_outcome_set
-            && ctx.accounts.event_data.outcome == EventOutcome::Void
-        {
-            if params.selling_price != avg_purchase_price {
+        if ctx.accounts.event_data.is_outcome_set {
+            // Once resolved, positions can only be settled at the outcome's price. These sells
+            // fill no user order, so there is no limit price to protect the user and the
+            // outcome is the only price the operator may use, for losing sides too.
+            let settlement_price = match ctx.accounts.event_data.outcome {
+                EventOutcome::Void => avg_purchase_price,
+                ref outcome if outcome.clone() as u8 - 1 == params.order_type as u8 => {
+                    event_total_price
+                }
+                _ => 0,
+            };
+            if params.selling_price != settlement_price {
                 return Err(SellOrderError::EventOutcomeMismatch.into());
             }
+        } else {
+            // Before that, every sell fills a sell order placed by the user
+            let order = ctx
+                .accounts
+                .order
+                .as_mut()
+                .ok_or_else(|| error!(SellOrderError::OrderRequired))?;
+
+            if !order.is_open() {
+                return Err(SellOrderError::OrderNotOpen.into());
+            }
+
+            if order.kind != OrderKind::Sell
+                || order.event_id != params.event_id
+                || order.order_type != params.order_type
+            {
+                return Err(SellOrderError::OrderMismatch.into());
+            }
+
+            if params.quantity == 0 || params.quantity > order.remaining_qty() {
+                return Err(SellOrderError::FillExceedsOrder.into());
+            }
+
+            // Never fill a sell below the user's limit price
+            if params.selling_price < order.price {
+                return Err(SellOrderError::PriceWorseThanLimit.into());
+            }
+
+            // Sell orders lock no funds, proceeds are paid out of the event escrow
+            order.record_fill(params.quantity, 0);
+            if !order.is_open() {
+                let user_profile = &mut ctx.accounts.user_profile;
+                user_profile.open_sell_orders = user_profile.open_sell_orders.saturating_sub(1);
+            }
+            ctx.accounts
+                .user_arka_event_account
+                .release_committed(params.order_type, params.quantity);
+
+            msg!(
+                "Filled sell order_id={:?} qty={:?}/{:?}, status={:?}",
+                order.order_id,
+                order.filled_qty,
+                order.quantity,
+                order.status,
+            );
         }
 
-        assert!(total_qty >= params.quantity);
+        if params.quantity > total_qty {
+            return Err(SellOrderError::InsufficientPosition.into());
+        }
 
-        let purchase_price = avg_purchase_price * params.quantity;
-        let selling_price = params.selling_price * params.quantity;
+        let purchase_price = ctx
+            .accounts
+            .user_arka_event_account
+            .cost_of(params.order_type, params.quantity);
+        let selling_price = params
+            .selling_price
+            .checked_mul(params.quantity)
+            .ok_or(SellOrderError::AmountOverflow)?;
 
-        let mut amount_to_return = selling_price;
         let mut commission = 0;
 
+        // Charged at the rate the position was bought with
+        let commission_bps = ctx.accounts.user_arka_event_account.commission_bps[order_type];
+
         // User is making a profit, thus we need to deduct commission
         if selling_price > purchase_price {
-            let commission_rate = ctx.accounts.user_arka_event_account.comission;
             let profit = selling_price - purchase_price;
-            commission = (commission_rate * profit) / 100;
-            amount_to_return = selling_price - commission;
+            commission = FeeConfig::apply_bps(profit, commission_bps);
         }
 
+        // Trading fees are charged on fills only, not when settling a resolved event
+        let fee_config = ctx
+            .accounts
+            .event_data
+            .fee_config(
+                ctx.accounts
+                    .event_fee_config
+                    .as_deref()
+                    .map(|fee_config| &**fee_config),
+                &ctx.accounts.fee_config,
+            )
+            .ok_or(SellOrderError::MissingEventFeeConfig)?;
+        let trading_fee = if ctx.accounts.event_data.is_outcome_set {
+            0
+        } else {
+            FeeConfig::apply_bps(
+                selling_price,
+                fee_config.trading_fee_bps(params.liquidity) as u64,
+            )
+        };
+        let referrer = ctx.accounts.user_profile.referrer;
+        let referral_fee = fee_config.referral_fee(commission + trading_fee, referrer);
+        let vault_fee = commission + trading_fee - referral_fee;
+
+        // The Token-2022 transfer fee on the move into the fee vault is charged to the seller
+        let vault_amount = gross_amount(&ctx.accounts.mint.to_account_info(), vault_fee)?;
+        let amount_to_return = selling_price
+            .checked_sub(vault_amount + referral_fee)
+            .ok_or(SellOrderError::FeesExceedProceeds)?;
+
+        // Promo principal goes back to the promo account first, only the rest is paid out as cash
+        let (promo_principal, _) = ctx
+            .accounts
+            .user_arka_event_account
+            .promo_share(params.order_type, params.quantity);
+        let promo_amount = promo_principal.min(amount_to_return);
+
         msg!(
-            "Purchase price: {:?}, Selling Price: {:?}, commission: {:?} promo_amount: {:?}",
+            "Purchase price: {:?}, Selling Price: {:?}, commission: {:?} ({:?} bps) trading fee: {:?} referral fee: {:?} promo_amount: {:?}",
             purchase_price,
             selling_price,
             commission,
-            params.promo_amount,
+            commission_bps,
+            trading_fee,
+            referral_fee,
+            promo_amount,
         );
 
         let bump = ctx.bumps.delegate.to_be_bytes();
@@ -539,70 +2273,155 @@ pub mod solana_ctf {
         let seeds = &[b"usdc_eid_", event_id.as_ref(), bump.as_ref()];
         let signer_seeds = [&seeds[..]];
 
-        if params.selling_price > 0 {
-            let cpi_accounts = token::Transfer {
-                to: ctx.accounts.arka_usdc_token_account.to_account_info(),
+        if vault_amount > 0 {
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
                 from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
                 authority: ctx.accounts.delegate.to_account_info(),
             };
 
             let cpi_context = CpiContext::new_with_signer(
-                ctx.accounts.old_token_program.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
                 cpi_accounts,
                 &signer_seeds,
             );
 
-            token::transfer(cpi_context, commission)?;
+            token_interface::transfer_checked(
+                cpi_context,
+                vault_amount,
+                ctx.accounts.mint.decimals,
+            )?;
         }
 
-        if let Some(usdc_account) = &ctx.accounts.user_usdc_token_account {
-            if amount_to_return - params.promo_amount > 0 {
-                let cpi_accounts = token::Transfer {
-                    to: usdc_account.to_account_info(),
-                    from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
-                    authority: ctx.accounts.delegate.to_account_info(),
-                };
+        if referral_fee > 0 {
+            let referrer_account = ctx
+                .accounts
+                .referrer_token_account
+                .as_ref()
+                .ok_or_else(|| error!(SellOrderError::MissingReferrerAccount))?;
 
-                let cpi_context = CpiContext::new_with_signer(
-                    ctx.accounts.old_token_program.to_account_info(),
-                    cpi_accounts,
-                    &signer_seeds,
-                );
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                to: referrer_account.to_account_info(),
+                from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
 
-                token::transfer(cpi_context, amount_to_return - params.promo_amount)?;
-            }
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                referral_fee,
+                ctx.accounts.mint.decimals,
+            )?;
         }
 
-        if let Some(promo_account) = &ctx.accounts.promo_account {
-            if params.promo_amount > 0 {
-                let cpi_accounts = token::Transfer {
-                    to: promo_account.to_account_info(),
-                    from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
-                    authority: ctx.accounts.delegate.to_account_info(),
-                };
+        ctx.accounts
+            .fee_ledger
+            .record(trading_fee, commission, referral_fee)?;
+
+        emit!(FeeCharged {
+            user_id: params.user_id,
+            event_id: params.event_id,
+            order_id: params.order_id,
+            kind: OrderKind::Sell,
+            liquidity: params.liquidity,
+            notional: selling_price,
+            trading_fee,
+            commission,
+            referrer,
+            referral_fee,
+        });
+
+        let cash_amount = amount_to_return
+            .checked_sub(promo_amount)
+            .ok_or(SellOrderError::PromoExceedsProceeds)?;
+        if cash_amount > 0 {
+            // Proceeds owed as cash can't be left behind in the event account
+            let usdc_account = ctx
+                .accounts
+                .user_usdc_token_account
+                .as_ref()
+                .ok_or_else(|| error!(SellOrderError::MissingUsdcAccount))?;
+
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                to: usdc_account.to_account_info(),
+                from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
 
-                let cpi_context = CpiContext::new_with_signer(
-                    ctx.accounts.old_token_program.to_account_info(),
-                    cpi_accounts,
-                    &signer_seeds,
-                );
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
 
-                token::transfer(cpi_context, params.promo_amount)?;
-            }
-        } else {
-            msg!("Promo account was not passed to this contract!");
+            token_interface::transfer_checked(
+                cpi_context,
+                cash_amount,
+                ctx.accounts.mint.decimals,
+            )?;
         }
 
-        /* Reduce Arka token quantity from user account */
-        ctx.accounts.user_arka_event_account.total_qty[order_type] -= params.quantity;
-        msg!(
-            "Total quantiy available after this trade={:?}",
-            ctx.accounts.user_arka_event_account.total_qty[order_type]
-        );
+        if promo_amount > 0 {
+            let promo_account = ctx
+                .accounts
+                .promo_account
+                .as_ref()
+                .ok_or_else(|| error!(SellOrderError::MissingPromoAccount))?;
 
-        Ok(())
-    }
-}
+            let cpi_accounts = TransferChecked {
+                mint: ctx.accounts.mint.to_account_info(),
+                to: promo_account.to_account_info(),
+                from: ctx.accounts.arka_usdc_event_token_account.to_account_info(),
+                authority: ctx.accounts.delegate.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            token_interface::transfer_checked(
+                cpi_context,
+                promo_amount,
+                ctx.accounts.mint.decimals,
+            )?;
+        }
+
+        /* Reduce Arka token quantity from user account */
+        let user_event_account = &mut ctx.accounts.user_arka_event_account;
+        let held_before = user_event_account.held_qty();
+        user_event_account.reduce_position(
+            params.order_type,
+            params.selling_price,
+            params.quantity,
+            commission,
+            trading_fee,
+        )?;
+        ctx.accounts
+            .event_data
+            .remove_outstanding(params.order_type, params.quantity);
+        msg!(
+            "Total quantiy available after this trade={:?}, realized pnl={:?}",
+            user_event_account.total_qty[order_type],
+            user_event_account.realized_pnl[order_type],
+        );
+
+        ctx.accounts
+            .user_profile
+            .track_position(held_before, &ctx.accounts.user_arka_event_account);
+
+        Ok(())
+    }
+}
 
 #[error_code]
 pub enum BuyOrderError {
@@ -610,6 +2429,26 @@ pub enum BuyOrderError {
     InvalidPrice,
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("User account is frozen.")]
+    UserFrozen,
+    #[msg("Order is not open.")]
+    OrderNotOpen,
+    #[msg("Fill does not match the order's event or side.")]
+    OrderMismatch,
+    #[msg("Fill quantity exceeds the order's remaining quantity.")]
+    FillExceedsOrder,
+    #[msg("Fill costs more than the funds still locked for the order.")]
+    InsufficientLockedFunds,
+    #[msg("Fill price is above the order's limit price.")]
+    PriceWorseThanLimit,
+    #[msg("User has a referrer but no referrer token account was passed.")]
+    MissingReferrerAccount,
+    #[msg("Event has its own fee config but it was not passed to this contract!")]
+    MissingEventFeeConfig,
+    #[msg("Event has its own position limits but they were not passed to this contract!")]
+    MissingEventPositionLimits,
+    #[msg("Order amount overflowed.")]
+    AmountOverflow,
 }
 
 #[error_code]
@@ -618,18 +2457,60 @@ pub enum TranferFromUserWalletError {
     Unauthorized,
     #[msg("Insufficient balance")]
     InsufficientBalance,
+    #[msg("User account is frozen.")]
+    UserFrozen,
+    #[msg("Amount does not match order price * quantity.")]
+    InvalidOrderAmount,
+    #[msg("Promo cap must be at most 10000 bps.")]
+    InvalidPromoPolicy,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
 }
 
 #[error_code]
 pub enum TranferFromUserPdaError {
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("Withdrawals are blocked for this user.")]
+    WithdrawalsBlocked,
+    #[msg("Order is not open.")]
+    OrderNotOpen,
+    #[msg("Released amount exceeds the funds still locked for the order.")]
+    ExceedsLockedFunds,
+    #[msg("Released promo amount does not match the promo still locked for the order.")]
+    MismatchedRefundSplit,
+    #[msg("Account to refund the order into was not passed to this contract!")]
+    MissingRefundAccount,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+    #[msg("Order was locked in a different mint.")]
+    MintMismatch,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+    #[msg("Order belongs to another user or order_id.")]
+    OrderMismatch,
+    #[msg("Promo can only be released back against the order it was locked for.")]
+    PromoRequiresOrder,
+    #[msg("Released amount exceeds the escrow funds not locked for open orders.")]
+    ExceedsFreeFunds,
+    #[msg("Every open order of the user must be passed, once each.")]
+    MissingOpenOrders,
+    #[msg("Released promo amount is larger than the released amount.")]
+    PromoExceedsAmount,
 }
 
 #[error_code]
 pub enum InitializeUserAtaError {
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+}
+
+#[error_code]
+pub enum MigrateUserEscrowError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
 }
 
 #[error_code]
@@ -642,6 +2523,12 @@ pub enum CloseEventDataError {
 pub enum CloseEventAccountError {
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("Event must be resolved before it can be closed.")]
+    EventNotResolved,
+    #[msg("Users still hold positions in the event.")]
+    OutstandingPositions,
+    #[msg("Event escrow must be swept before the event can be closed.")]
+    EscrowNotEmpty,
 }
 
 #[error_code]
@@ -656,6 +2543,34 @@ pub enum SellOrderError {
     EventOutcomeMismatch,
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("Sells before the event is resolved must fill a sell order.")]
+    OrderRequired,
+    #[msg("Order is not open.")]
+    OrderNotOpen,
+    #[msg("Fill does not match the order's event or side.")]
+    OrderMismatch,
+    #[msg("Fill quantity exceeds the order's remaining quantity.")]
+    FillExceedsOrder,
+    #[msg("Fill price is below the order's limit price.")]
+    PriceWorseThanLimit,
+    #[msg("Fees are larger than the selling price.")]
+    FeesExceedProceeds,
+    #[msg("User has a referrer but no referrer token account was passed.")]
+    MissingReferrerAccount,
+    #[msg("Position was funded with promo but no promo account was passed.")]
+    MissingPromoAccount,
+    #[msg("User does not hold enough quantity to sell.")]
+    InsufficientPosition,
+    #[msg("Event has its own fee config but it was not passed to this contract!")]
+    MissingEventFeeConfig,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+    #[msg("Proceeds are owed in cash but no user USDC token account was passed.")]
+    MissingUsdcAccount,
+    #[msg("Promo returned is larger than the proceeds.")]
+    PromoExceedsProceeds,
+    #[msg("Selling amount overflowed.")]
+    AmountOverflow,
 }
 
 #[repr(u8)]
@@ -674,355 +2589,2825 @@ impl OrderType {
     }
 }
 
-#[derive(Accounts)]
-pub struct UpdateOutcome<'info> {
-    /// CHECK: This account is safe since this is our owner account.
-    #[account(signer)]
-    pub owner: Signer<'info>,
-    #[account(mut)]
-    pub event_data: Account<'info, EventData>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
+#[account]
+pub struct Config {
+    pub version: u8,
+    pub usdc_mint: Pubkey,
+    pub treasury: Pubkey,
+    pub arka_usdc_wallet: Pubkey,
+    // Seconds a user has to wait before executing their own withdrawal
+    pub withdrawal_delay: i64,
+    // Treasury role allowed to withdraw collected fees
+    pub fee_authority: Pubkey,
+    // Caps on every user's position in any event
+    pub position_limits: PositionLimits,
+    // Most promo a user can be granted over their lifetime, zero means unlimited
+    pub max_promo_per_user: u64,
+    // Stablecoins events can be denominated in besides `usdc_mint`, unused slots are zeroed
+    pub collateral_mints: [Pubkey; Config::MAX_COLLATERALS],
+    // Room for new fields, taken from the end so existing accounts read them as zero
+    pub reserved: [u8; 64],
+}
+
+impl Config {
+    pub const LEN: usize = std::mem::size_of::<Config>();
+    pub const VERSION: u8 = 1;
+    pub const MAX_COLLATERALS: usize = 4;
+
+    pub fn is_collateral(&self, mint: &Pubkey) -> bool {
+        *mint == self.usdc_mint
+            || (*mint != Pubkey::default() && self.collateral_mints.contains(mint))
+    }
+
+    pub fn check_promo_cap(&self, user_profile: &UserProfile, amount: u64) -> Result<()> {
+        let granted = user_profile
+            .promo_granted
+            .checked_add(amount)
+            .ok_or(PromoGrantError::PromoCapExceeded)?;
+        if self.max_promo_per_user > 0 && granted > self.max_promo_per_user {
+            msg!(
+                "Promo granted {:?} + {:?} exceeds max_promo_per_user={:?}",
+                user_profile.promo_granted,
+                amount,
+                self.max_promo_per_user,
+            );
+            return Err(PromoGrantError::PromoCapExceeded.into());
+        }
+        Ok(())
+    }
 }
 
 #[error_code]
-pub enum UpdateOutcomeError {
-    #[msg("Trying to set outcome to Null, not allowed.")]
-    InvalidOutcomeState,
-    #[msg("You are only allowed to update once.")]
-    OutcomeAlreadyUpdated,
+pub enum ConfigError {
     #[msg("Unauthorized: Only the owner can execute this instruction.")]
     Unauthorized,
+    #[msg("Withdrawal delay can not be negative.")]
+    InvalidWithdrawalDelay,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct InitEventParams {
-    pub event_id: u64,
-    pub event_total_price: u64,
+#[account]
+pub struct FeeConfig {
+    pub version: u8,
+    // Commission on profits in basis points, indexed by the user's KYC tier
+    pub tier_commission_bps: [u16; FeeConfig::TIERS],
+    // Fees on the notional of every fill in basis points
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    // Share of all fees paid to the user's referrer in basis points
+    pub referral_share_bps: u16,
 }
 
-#[repr(u8)]
-#[derive(Default, Debug, PartialEq, Eq, Clone, AnchorSerialize, AnchorDeserialize)]
-pub enum EventOutcome {
-    #[default]
-    Null = 0,
-    Yes,
-    No,
-    Void,
-}
+impl FeeConfig {
+    pub const LEN: usize = std::mem::size_of::<FeeConfig>();
+    pub const VERSION: u8 = 1;
 
-impl From<u8> for EventOutcome {
-    fn from(value: u8) -> Self {
-        match value {
-            0 => EventOutcome::Null,
-            1 => EventOutcome::Yes,
-            2 => EventOutcome::No,
-            3 => EventOutcome::Void,
-            _ => EventOutcome::Null, // Default case if value doesn't match
+    pub const TIERS: usize = 4;
+    pub const MAX_COMMISSION_BPS: u16 = 10_000;
+
+    pub fn set(&mut self, params: &FeeConfigParams) -> Result<()> {
+        if params
+            .tier_commission_bps
+            .iter()
+            .any(|bps| *bps > Self::MAX_COMMISSION_BPS)
+        {
+            return Err(FeeConfigError::InvalidCommissionRate.into());
+        }
+
+        if params.maker_fee_bps > Self::MAX_COMMISSION_BPS
+            || params.taker_fee_bps > Self::MAX_COMMISSION_BPS
+            || params.referral_share_bps > Self::MAX_COMMISSION_BPS
+        {
+            return Err(FeeConfigError::InvalidFeeRate.into());
+        }
+
+        self.version = Self::VERSION;
+        self.tier_commission_bps = params.tier_commission_bps;
+        self.maker_fee_bps = params.maker_fee_bps;
+        self.taker_fee_bps = params.taker_fee_bps;
+        self.referral_share_bps = params.referral_share_bps;
+        Ok(())
+    }
+
+    pub fn trading_fee_bps(&self, liquidity: Liquidity) -> u16 {
+        match liquidity {
+            Liquidity::Maker => self.maker_fee_bps,
+            Liquidity::Taker => self.taker_fee_bps,
+        }
+    }
+
+    // Part of the fees owed to the referrer, if the user has one
+    pub fn referral_fee(&self, fees: u64, referrer: Option<Pubkey>) -> u64 {
+        match referrer {
+            Some(_) => Self::apply_bps(fees, self.referral_share_bps as u64),
+            None => 0,
         }
     }
+
+    // Tiers above the last configured one pay the last tier's rate
+    pub fn commission_bps(&self, kyc_tier: u8) -> u16 {
+        let tier = (kyc_tier as usize).min(Self::TIERS - 1);
+        self.tier_commission_bps[tier]
+    }
+
+    pub fn apply_bps(amount: u64, bps: u64) -> u64 {
+        ((amount as u128 * bps as u128) / 10_000) as u64
+    }
 }
 
-#[account]
-pub struct EventData {
+#[repr(u8)]
+#[derive(
+    Debug, PartialEq, Eq, Clone, Copy, AnchorSerialize, AnchorDeserialize, serde::Deserialize,
+)]
+pub enum Liquidity {
+    Maker = 0,
+    Taker,
+}
+
+#[event]
+pub struct FeeCharged {
+    pub user_id: u64,
     pub event_id: u64,
-    pub outcome: EventOutcome,
-    pub is_outcome_set: bool,
-    pub event_total_price: u64,
+    pub order_id: u64,
+    pub kind: OrderKind,
+    pub liquidity: Liquidity,
+    pub notional: u64,
+    pub trading_fee: u64,
+    pub commission: u64,
+    pub referrer: Option<Pubkey>,
+    pub referral_fee: u64,
 }
 
-impl EventData {
-    pub const LEN: usize = std::mem::size_of::<EventData>();
+// Running totals of the fees charged, so the fee vault can be reconciled against trades
+#[account]
+pub struct FeeLedger {
+    pub version: u8,
+    // Collateral the vault holds fees in, and the treasury account they are withdrawn to
+    pub mint: Pubkey,
+    pub treasury: Pubkey,
+    pub trading_fees: u64,
+    pub commission: u64,
+    pub referral_fees: u64,
+    pub withdrawn: u64,
 }
 
-#[error_code]
-pub enum InitializeEventError {
-    #[msg("Trying to set commission rate > 100")]
-    InvalidCommissionRate,
-    #[msg("Unauthorized: Only the owner can execute this instruction.")]
-    Unauthorized,
+impl FeeLedger {
+    pub const LEN: usize = std::mem::size_of::<FeeLedger>();
+    pub const VERSION: u8 = 1;
+
+    pub fn record(&mut self, trading_fee: u64, commission: u64, referral_fee: u64) -> Result<()> {
+        let add = |total: u64, fee: u64| {
+            total
+                .checked_add(fee)
+                .ok_or_else(|| error!(FeeVaultError::LedgerOverflow))
+        };
+        self.trading_fees = add(self.trading_fees, trading_fee)?;
+        self.commission = add(self.commission, commission)?;
+        self.referral_fees = add(self.referral_fees, referral_fee)?;
+        Ok(())
+    }
+
+    // Fees paid into the vault, referral shares go straight to the referrers
+    pub fn collected(&self) -> u64 {
+        self.trading_fees + self.commission - self.referral_fees
+    }
+
+    // What the fee vault should hold
+    pub fn balance(&self) -> u64 {
+        self.collected() - self.withdrawn
+    }
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct InitUserAtaParams {
-    pub user_id: u64,
-    pub promo_balance: u64,
+#[event]
+pub struct FeesWithdrawn {
+    pub amount: u64,
+    pub treasury: Pubkey,
+    pub total_withdrawn: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct InitPromoAccount {
-    pub user_id: u64,
+#[error_code]
+pub enum FeeVaultError {
+    #[msg("Unauthorized: Only the fee authority can execute this instruction.")]
+    Unauthorized,
+    #[msg("Fee vault does not hold enough fees.")]
+    InsufficientFees,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+    #[msg("The USDC fee vault must pay out to the treasury in the config.")]
+    InvalidTreasury,
+    #[msg("Fee ledger total overflowed.")]
+    LedgerOverflow,
 }
 
 #[derive(Accounts)]
-#[instruction(params: InitUserAtaParams)]
-pub struct InitializePromoAccount<'info> {
+pub struct InitializeFeeVault<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
-    pub usdc_mint: Box<Account<'info, OldMint>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(constraint = config.is_collateral(&mint.key()) @ FeeVaultError::InvalidCollateral)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        token::mint = mint,
+        constraint = mint.key() != config.usdc_mint || treasury.key() == config.treasury @ FeeVaultError::InvalidTreasury,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        seeds = [b"fees", mint.key().as_ref()],
         bump,
         payer = payer,
-        token::mint = usdc_mint,
-        token::authority = promo_delegate,
+        token::mint = mint,
+        token::authority = delegate,
     )]
-    pub promo_account: Box<Account<'info, OldTokenAccount>>,
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        init,
+        payer = payer,
+        space = 8 + FeeLedger::LEN,
+        seeds = [b"fee_ledger", mint.key().as_ref()],
         bump,
     )]
+    pub fee_ledger: Box<Account<'info, FeeLedger>>,
+    #[account(seeds = [b"fees", mint.key().as_ref()], bump)]
     /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    pub promo_delegate: AccountInfo<'info>,
+    pub delegate: AccountInfo<'info>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, OldToken>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct WithdrawFeesParams {
+    pub amount: u64,
 }
 
 #[derive(Accounts)]
-#[instruction(params: InitUserAtaParams)]
-pub struct InitializeUserAta<'info> {
+pub struct WithdrawFees<'info> {
+    #[account(signer)]
+    pub fee_authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"fees", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, seeds = [b"fee_ledger", mint.key().as_ref()], bump)]
+    pub fee_ledger: Box<Account<'info, FeeLedger>>,
+    // Fees can only be withdrawn to the treasury registered with the vault
+    #[account(
+        mut,
+        address = fee_ledger.treasury,
+        token::mint = mint,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(seeds = [b"fees", mint.key().as_ref()], bump)]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub delegate: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[error_code]
+pub enum FeeConfigError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Trying to set commission rate > 100%")]
+    InvalidCommissionRate,
+    #[msg("Trying to set a trading fee or referral share > 100%")]
+    InvalidFeeRate,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct FeeConfigParams {
+    pub tier_commission_bps: [u16; FeeConfig::TIERS],
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    pub referral_share_bps: u16,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeConfig<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
-    pub usdc_mint: Box<Account<'info, OldMint>>,
     #[account(
-        init,
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
-        bump,
+        init_if_needed,
         payer = payer,
-        token::mint = usdc_mint,
-        token::authority = delegate,
+        space = 8 + FeeConfig::LEN,
+        seeds = [b"fee_config"],
+        bump,
     )]
-    pub escrow_account: Box<Account<'info, OldTokenAccount>>,
+    pub fee_config: Account<'info, FeeConfig>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct EventFeeConfigParams {
+    pub event_id: u64,
+    pub fees: FeeConfigParams,
+}
+
+#[derive(Accounts)]
+#[instruction(params: EventFeeConfigParams)]
+pub struct SetEventFeeConfig<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
     #[account(
-        init,
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+    #[account(
+        init_if_needed,
         payer = payer,
-        token::mint = usdc_mint,
-        token::authority = promo_delegate,
+        space = 8 + FeeConfig::LEN,
+        seeds = [b"fee_config_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
     )]
-    pub promo_account: Box<Account<'info, OldTokenAccount>>,
+    pub event_fee_config: Account<'info, FeeConfig>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct CloseEventFeeConfigParams {
+    pub event_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseEventFeeConfigParams)]
+pub struct CloseEventFeeConfig<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
     #[account(
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
     )]
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    pub delegate: AccountInfo<'info>,
+    pub event_data: Account<'info, EventData>,
     #[account(
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        mut,
+        seeds = [b"fee_config_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+        close = payer,
     )]
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    pub promo_delegate: AccountInfo<'info>,
-    #[account(mut)]
-    pub arka_usdc_wallet: Box<Account<'info, OldTokenAccount>>,
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    #[account(seeds = [b"money"], bump)]
-    pub arka_delegate: AccountInfo<'info>,
+    pub event_fee_config: Account<'info, FeeConfig>,
     #[account(mut)]
     pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, OldToken>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct TranferFromUserWalletParams {
-    pub user_id: u64,
-    pub amount: u64,
-    pub event_id: u64,
-    pub order_id: u64,
-    pub promo_amount: u64,
+pub struct ConfigParams {
+    pub withdrawal_delay: i64,
+    pub fee_authority: Pubkey,
+    pub position_limits: PositionLimits,
+    pub max_promo_per_user: u64,
+    pub collateral_mints: [Pubkey; Config::MAX_COLLATERALS],
+}
+
+// A limit of zero means unlimited
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PositionLimits {
+    // Most units a user can hold on one side of an event
+    pub max_qty_per_side: u64,
+    // Most a user can have invested in an event, both sides together
+    pub max_notional: u64,
+}
+
+impl PositionLimits {
+    pub fn check(
+        &self,
+        position: &UserEventData,
+        order_type: OrderType,
+        quantity: u64,
+        notional: u64,
+    ) -> Result<()> {
+        let qty = position.total_qty[order_type as usize] as u128 + quantity as u128;
+        if self.max_qty_per_side > 0 && qty > self.max_qty_per_side as u128 {
+            msg!(
+                "Position of {:?} exceeds max_qty_per_side={:?}",
+                qty,
+                self.max_qty_per_side
+            );
+            return Err(PositionLimitsError::PositionLimitExceeded.into());
+        }
+
+        let exposure = position.cost_basis[0] + position.cost_basis[1] + notional as u128;
+        if self.max_notional > 0 && exposure > self.max_notional as u128 {
+            msg!(
+                "Exposure of {:?} exceeds max_notional={:?}",
+                exposure,
+                self.max_notional
+            );
+            return Err(PositionLimitsError::ExposureLimitExceeded.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct EventPositionLimits {
+    pub version: u8,
+    pub limits: PositionLimits,
+}
+
+impl EventPositionLimits {
+    pub const LEN: usize = std::mem::size_of::<EventPositionLimits>();
+    pub const VERSION: u8 = 1;
+}
+
+#[error_code]
+pub enum PositionLimitsError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Fill would take the user's position over the quantity limit.")]
+    PositionLimitExceeded,
+    #[msg("Fill would take the user's exposure over the notional limit.")]
+    ExposureLimitExceeded,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct EventPositionLimitsParams {
+    pub event_id: u64,
+    pub limits: PositionLimits,
+}
+
+#[derive(Accounts)]
+#[instruction(params: EventPositionLimitsParams)]
+pub struct SetEventPositionLimits<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + EventPositionLimits::LEN,
+        seeds = [b"limits_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_position_limits: Account<'info, EventPositionLimits>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct CloseEventPositionLimitsParams {
+    pub event_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseEventPositionLimitsParams)]
+pub struct CloseEventPositionLimits<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+    #[account(
+        mut,
+        seeds = [b"limits_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+        close = payer,
+    )]
+    pub event_position_limits: Account<'info, EventPositionLimits>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    // constant 8 in space denotes the size of the discriminator
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Config::LEN,
+        seeds = [b"config"],
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+    pub usdc_mint: Box<InterfaceAccount<'info, Mint>>,
+    // Token account collected fees are withdrawn to
+    #[account(token::mint = usdc_mint)]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Token account which has approved the `money` delegate, used to fund promo balances
+    #[account(token::mint = usdc_mint)]
+    pub arka_usdc_wallet: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(mut, seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+    #[account(token::mint = config.usdc_mint)]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(token::mint = config.usdc_mint)]
+    pub arka_usdc_wallet: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[account]
+pub struct UserProfile {
+    pub version: u8,
+    pub user_id: u64,
+    pub wallet: Pubkey,
+    pub created_at: i64,
+    pub kyc_tier: u8,
+    pub status: u8,
+    // Highest nonce consumed by a user-signed intent
+    pub intent_nonce: u64,
+    // Receives a share of the fees paid by this user
+    pub referrer: Option<Pubkey>,
+    // Notional of all buys, counted towards promo wagering requirements
+    pub wagered_volume: u64,
+    // Promo granted over the user's lifetime, bounded by `Config.max_promo_per_user`
+    pub promo_granted: u64,
+    // Buy orders which still hold funds in one of the user's escrows
+    pub open_orders: u64,
+    // Promo granted and not yet returned or expired, spent promo included
+    pub promo_outstanding: u64,
+    // Latest expiry of the grants behind `promo_outstanding`, zero once nothing is outstanding
+    pub promo_expires_at: i64,
+    // Events the user holds units of, and sell orders still resting on them
+    pub open_positions: u64,
+    pub open_sell_orders: u64,
+    pub reserved: [u8; 24],
+}
+
+impl UserProfile {
+    pub const LEN: usize = std::mem::size_of::<UserProfile>();
+    pub const VERSION: u8 = 1;
+
+    // User can not lock funds or trade
+    pub const STATUS_FROZEN: u8 = 1 << 0;
+    // Funds can not be released to the user's wallet
+    pub const STATUS_WITHDRAWALS_BLOCKED: u8 = 1 << 1;
+    // Every order lock, sell order and release must carry an intent signed by the user's wallet
+    pub const STATUS_REQUIRES_INTENT: u8 = 1 << 2;
+
+    pub fn is_frozen(&self) -> bool {
+        self.status & Self::STATUS_FROZEN != 0
+    }
+
+    pub fn can_withdraw(&self) -> bool {
+        self.status & (Self::STATUS_FROZEN | Self::STATUS_WITHDRAWALS_BLOCKED) == 0
+    }
+
+    pub fn requires_intent(&self) -> bool {
+        self.status & Self::STATUS_REQUIRES_INTENT != 0
+    }
+
+    /// Stops counting `order` as open once the last of the `locked_before` funds left its escrow.
+    pub fn release_order(&mut self, locked_before: u64, order: &Order) {
+        if locked_before > 0 && order.remaining_locked() == 0 {
+            self.open_orders = self.open_orders.saturating_sub(1);
+        }
+    }
+
+    /// Counts `position` as open while it holds any units, `held_before` being what it held
+    /// before the trade.
+    pub fn track_position(&mut self, held_before: u64, position: &UserEventData) {
+        let held = position.held_qty();
+        if held_before == 0 && held > 0 {
+            self.open_positions += 1;
+        } else if held_before > 0 && held == 0 {
+            self.open_positions = self.open_positions.saturating_sub(1);
+        }
+    }
+
+    /// Sums what the user's open orders still hold in their escrow of `mint`. All of the
+    /// `open_orders` must be passed in `orders`, so none can be left out of the sum.
+    pub fn locked_in_escrow(&self, mint: &Pubkey, orders: &[AccountInfo]) -> Result<u64> {
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(orders.len());
+        let mut locked: u64 = 0;
+        for info in orders {
+            if *info.owner != crate::ID || seen.contains(info.key) {
+                return Err(TranferFromUserPdaError::MissingOpenOrders.into());
+            }
+            let order = Order::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            if order.user_id != self.user_id || order.remaining_locked() == 0 {
+                return Err(TranferFromUserPdaError::MissingOpenOrders.into());
+            }
+            if order.mint == *mint {
+                locked = locked
+                    .checked_add(order.remaining_locked())
+                    .ok_or(TranferFromUserPdaError::ExceedsFreeFunds)?;
+            }
+            seen.push(*info.key);
+        }
+        if seen.len() as u64 != self.open_orders {
+            msg!(
+                "Passed {:?} open orders, user has open_orders={:?}",
+                seen.len(),
+                self.open_orders
+            );
+            return Err(TranferFromUserPdaError::MissingOpenOrders.into());
+        }
+        Ok(locked)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum IntentAction {
+    BuyOrder {
+        order_id: u64,
+        event_id: u64,
+        order_type: OrderType,
+        price: u64,
+        quantity: u64,
+        amount: u64,
+    },
+    SellOrder {
+        order_id: u64,
+        event_id: u64,
+        order_type: OrderType,
+        price: u64,
+        quantity: u64,
+    },
+    Withdraw {
+        mint: Pubkey,
+        order_id: u64,
+        utr_id: String,
+        amount: u64,
+        promo_amount: u64,
+    },
+    UpdateProfile {
+        wallet: Pubkey,
+        status: u8,
+    },
+}
+
+// Off-chain message signed by the user's wallet, verified on-chain through
+// an Ed25519 program instruction placed right before ours
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserIntent {
+    pub program_id: Pubkey,
+    pub user_id: u64,
+    pub nonce: u64,
+    pub action: IntentAction,
+}
+
+impl UserIntent {
+    pub fn message(&self) -> Vec<u8> {
+        self.try_to_vec().expect("Failed to serialize intent")
+    }
+}
+
+#[error_code]
+pub enum IntentError {
+    #[msg("User requires a signed intent for this instruction.")]
+    IntentRequired,
+    #[msg("Instructions sysvar was not passed to this contract!")]
+    MissingInstructionsSysvar,
+    #[msg("Intent nonce has already been used.")]
+    NonceAlreadyUsed,
+    #[msg("Previous instruction is not an Ed25519 signature verification.")]
+    MissingSignature,
+    #[msg("Ed25519 instruction does not match the user's wallet and intent.")]
+    InvalidSignature,
+}
+
+const ED25519_OFFSETS_START: usize = 2;
+const ED25519_OFFSETS_LEN: usize = 14;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+}
+
+#[error_code]
+pub enum TokenTransferError {
+    #[msg("Settlement mint could not be read.")]
+    InvalidMint,
+    #[msg("Transfer fee calculation overflowed.")]
+    FeeOverflow,
+}
+
+/// The Token-2022 transfer fee the mint charges in `epoch`. Legacy SPL mints
+/// and Token-2022 mints without the transfer fee extension charge none.
+pub fn epoch_transfer_fee(mint_data: &[u8], epoch: u64) -> Result<Option<TransferFee>> {
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(mint_data)
+        .map_err(|_| error!(TokenTransferError::InvalidMint))?;
+    Ok(mint
+        .get_extension::<TransferFeeConfig>()
+        .ok()
+        .map(|config| *config.get_epoch_fee(epoch)))
+}
+
+/// Amount that arrives when `amount` is sent.
+pub fn amount_after_fee(mint_data: &[u8], epoch: u64, amount: u64) -> Result<u64> {
+    match epoch_transfer_fee(mint_data, epoch)? {
+        Some(fee) => fee
+            .calculate_post_fee_amount(amount)
+            .ok_or_else(|| error!(TokenTransferError::FeeOverflow)),
+        None => Ok(amount),
+    }
+}
+
+/// Amount to send so that `amount` arrives.
+pub fn amount_before_fee(mint_data: &[u8], epoch: u64, amount: u64) -> Result<u64> {
+    match epoch_transfer_fee(mint_data, epoch)? {
+        Some(fee) => fee
+            .calculate_pre_fee_amount(amount)
+            .ok_or_else(|| error!(TokenTransferError::FeeOverflow)),
+        None => Ok(amount),
+    }
+}
+
+pub fn received_amount(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    amount_after_fee(&mint.try_borrow_data()?, Clock::get()?.epoch, amount)
+}
+
+pub fn gross_amount(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    amount_before_fee(&mint.try_borrow_data()?, Clock::get()?.epoch, amount)
+}
+
+/// Checks that `ix` is an Ed25519 program instruction verifying a single
+/// signature by `signer` over exactly `message`. The Ed25519 program itself
+/// fails the transaction if the signature is invalid, so matching the
+/// public key and message is all that is left to do here.
+pub fn verify_ed25519_ix(ix: &Instruction, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    if ix.program_id != ed25519_program::ID || !ix.accounts.is_empty() {
+        return Err(IntentError::MissingSignature.into());
+    }
+
+    let data = &ix.data;
+    if data.len() < ED25519_OFFSETS_START + ED25519_OFFSETS_LEN || data[0] != 1 {
+        return Err(IntentError::InvalidSignature.into());
+    }
+
+    let offsets = ED25519_OFFSETS_START;
+    let (
+        Some(signature_ix_index),
+        Some(public_key_offset),
+        Some(public_key_ix_index),
+        Some(message_offset),
+        Some(message_size),
+        Some(message_ix_index),
+    ) = (
+        read_u16(data, offsets + 2),
+        read_u16(data, offsets + 4),
+        read_u16(data, offsets + 6),
+        read_u16(data, offsets + 8),
+        read_u16(data, offsets + 10),
+        read_u16(data, offsets + 12),
+    )
+    else {
+        return Err(IntentError::InvalidSignature.into());
+    };
+
+    // Signature, key and message must all live inside the Ed25519 instruction itself
+    let this_ix = u16::MAX as usize;
+    if signature_ix_index != this_ix
+        || public_key_ix_index != this_ix
+        || message_ix_index != this_ix
+    {
+        return Err(IntentError::InvalidSignature.into());
+    }
+
+    let public_key = data.get(public_key_offset..public_key_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    if public_key != Some(signer.as_ref()) || signed_message != Some(message) {
+        return Err(IntentError::InvalidSignature.into());
+    }
+
+    Ok(())
+}
+
+/// Verifies the user signed `action` when a nonce is supplied or the user
+/// requires intents, and consumes the nonce so the intent can't be replayed.
+pub fn verify_user_intent<'info>(
+    user_profile: &mut UserProfile,
+    intent_nonce: Option<u64>,
+    action: IntentAction,
+    instructions_sysvar: Option<&UncheckedAccount<'info>>,
+) -> Result<()> {
+    let nonce = match intent_nonce {
+        Some(nonce) => nonce,
+        None if user_profile.requires_intent() => {
+            return Err(IntentError::IntentRequired.into());
+        }
+        None => return Ok(()),
+    };
+
+    if nonce <= user_profile.intent_nonce {
+        return Err(IntentError::NonceAlreadyUsed.into());
+    }
+
+    let instructions_sysvar = instructions_sysvar
+        .ok_or_else(|| error!(IntentError::MissingInstructionsSysvar))?
+        .to_account_info();
+
+    let current_index = load_current_index_checked(&instructions_sysvar)?;
+    if current_index == 0 {
+        return Err(IntentError::MissingSignature.into());
+    }
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, &instructions_sysvar)?;
+
+    let intent = UserIntent {
+        program_id: crate::ID,
+        user_id: user_profile.user_id,
+        nonce,
+        action,
+    };
+    verify_ed25519_ix(&ed25519_ix, &user_profile.wallet, &intent.message())?;
+
+    user_profile.intent_nonce = nonce;
+
+    msg!(
+        "Verified intent for user_id={:?} nonce={:?}",
+        user_profile.user_id,
+        nonce
+    );
+
+    Ok(())
+}
+
+#[error_code]
+pub enum UpdateUserProfileError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Changing the wallet or dropping the intent requirement needs the current wallet's consent.")]
+    WalletConsentRequired,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct UpdateUserProfileParams {
+    pub user_id: u64,
+    pub wallet: Pubkey,
+    pub kyc_tier: u8,
+    pub status: u8,
+    pub referrer: Option<Pubkey>,
+    pub intent_nonce: Option<u64>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: UpdateUserProfileParams)]
+pub struct UpdateUserProfile<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserProfile::LEN,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Account<'info, UserProfile>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    // The wallet currently bound to the profile, when it signs the update itself
+    pub wallet: Option<Signer<'info>>,
+    /// CHECK: Address is checked against the instructions sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+}
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
+pub enum OrderKind {
+    Buy = 0,
+    Sell,
+}
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
+pub enum OrderStatus {
+    Open = 0,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
+#[account]
+pub struct Order {
+    pub version: u8,
+    pub order_id: u64,
+    pub user_id: u64,
+    pub event_id: u64,
+    // Collateral of the event, the mint the funds are locked in
+    pub mint: Pubkey,
+    pub kind: OrderKind,
+    pub order_type: OrderType,
+    // Limit price: the most a buy pays, the least a sell accepts, per unit
+    pub price: u64,
+    pub quantity: u64,
+    pub filled_qty: u64,
+    // Funds locked into the user escrow for this order, by source
+    pub usdc_amount: u64,
+    pub promo_amount: u64,
+    // Part of the locked funds already moved to the event escrow by fills
+    pub filled_amount: u64,
+    // Part of the locked funds returned to the user on cancellation, and how much of it was promo
+    pub refunded_amount: u64,
+    pub refunded_promo: u64,
+    pub status: OrderStatus,
+    pub created_at: i64,
+}
+
+impl Order {
+    pub const LEN: usize = std::mem::size_of::<Order>();
+    pub const VERSION: u8 = 1;
+
+    pub fn locked_amount(&self) -> u64 {
+        self.usdc_amount + self.promo_amount
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self.status,
+            OrderStatus::Open | OrderStatus::PartiallyFilled
+        )
+    }
+
+    pub fn remaining_qty(&self) -> u64 {
+        self.quantity - self.filled_qty
+    }
+
+    pub fn remaining_locked(&self) -> u64 {
+        self.locked_amount() - self.filled_amount - self.refunded_amount
+    }
+
+    pub fn record_fill(&mut self, quantity: u64, amount: u64) {
+        self.filled_qty += quantity;
+        self.filled_amount += amount;
+        self.status = if self.remaining_qty() == 0 {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+    }
+
+    /// Splits the funds still locked for this order into (usdc, promo).
+    /// Fills spend promo first, the same order the funds were locked in.
+    pub fn unfilled_split(&self) -> (u64, u64) {
+        let promo = self
+            .promo_amount
+            .saturating_sub(self.filled_amount)
+            .saturating_sub(self.refunded_promo)
+            .min(self.remaining_locked());
+        let usdc = self.remaining_locked() - promo;
+        (usdc, promo)
+    }
+
+    /// Splits a refund of `amount` into (usdc, promo), restoring promo first.
+    /// Returns `None` if more than the funds still locked is refunded.
+    pub fn refund_split(&self, amount: u64) -> Option<(u64, u64)> {
+        if amount > self.remaining_locked() {
+            return None;
+        }
+        let (_, unfilled_promo) = self.unfilled_split();
+        let promo = amount.min(unfilled_promo);
+        Some((amount - promo, promo))
+    }
+
+    pub fn record_refund(&mut self, usdc_amount: u64, promo_amount: u64) {
+        self.refunded_amount += usdc_amount + promo_amount;
+        self.refunded_promo += promo_amount;
+        if self.status != OrderStatus::Filled {
+            self.status = OrderStatus::Cancelled;
+        }
+    }
+}
+
+#[error_code]
+pub enum OrderError {
+    #[msg("Unauthorized: Only the owner or the user can execute this instruction.")]
+    Unauthorized,
+    #[msg("Order is not open.")]
+    OrderNotOpen,
+    #[msg("Order is still open, cancel or fill it before closing.")]
+    OrderStillOpen,
+    #[msg("Account to refund the order into was not passed to this contract!")]
+    MissingRefundAccount,
+    #[msg("Position of the sell order was not passed to this contract!")]
+    MissingPosition,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct CancelOrderParams {
+    pub order_id: u64,
+    pub user_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CancelOrderParams)]
+pub struct CancelOrder<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = order.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        mut,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = order.user_id == params.user_id @ OrderError::Unauthorized,
+    )]
+    pub order: Box<Account<'info, Order>>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user_profile.wallet,
+    )]
+    pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = mint.key() == config.usdc_mint @ OrderError::PromoRequiresUsdc,
+        token::mint = mint,
+    )]
+    pub promo_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Only needed for sell orders
+    #[account(
+        mut,
+        seeds = [b"uid_", params.user_id.to_le_bytes().as_ref(), b"_eid_", order.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_event_data: Option<Box<Account<'info, UserEventData>>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct CloseOrderParams {
+    pub order_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseOrderParams)]
+pub struct CloseOrder<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+        close = payer,
+    )]
+    pub order: Account<'info, Order>,
+    #[account(mut, signer)]
+    pub payer: Signer<'info>,
+}
+
+#[account]
+pub struct ReleaseReceipt {
+    pub version: u8,
+    pub utr_hash: [u8; 32],
+    pub order_id: u64,
+    pub user_id: u64,
+    pub event_id: u64,
+    pub usdc_amount: u64,
+    pub promo_amount: u64,
+    pub created_at: i64,
+}
+
+impl ReleaseReceipt {
+    pub const LEN: usize = std::mem::size_of::<ReleaseReceipt>();
+    pub const VERSION: u8 = 1;
+}
+
+#[error_code]
+pub enum ReleaseReceiptError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct CloseReleaseReceiptParams {
+    pub utr_id: String,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseReleaseReceiptParams)]
+pub struct CloseReleaseReceipt<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"utr_", hash(params.utr_id.as_bytes()).as_ref()],
+        bump,
+        close = payer,
+    )]
+    pub release_receipt: Account<'info, ReleaseReceipt>,
+    #[account(mut, signer)]
+    pub payer: Signer<'info>,
+}
+
+#[account]
+pub struct WithdrawalRequest {
+    pub version: u8,
+    pub user_id: u64,
+    pub mint: Pubkey,
+    pub requested_at: i64,
+}
+
+impl WithdrawalRequest {
+    pub const LEN: usize = std::mem::size_of::<WithdrawalRequest>();
+    pub const VERSION: u8 = 1;
+}
+
+#[error_code]
+pub enum WithdrawalError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Signer is not the wallet registered for this user.")]
+    WalletMismatch,
+    #[msg("Withdrawal delay has not passed yet.")]
+    WithdrawalLocked,
+    #[msg("User escrow still holds funds, pay the user out before settling.")]
+    EscrowNotSettled,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+    #[msg("User still has open orders, cancel them before withdrawing.")]
+    OrdersStillOpen,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct WithdrawalParams {
+    pub user_id: u64,
+    pub mint: Pubkey,
+}
+
+#[derive(Accounts)]
+#[instruction(params: WithdrawalParams)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = config.is_collateral(&params.mint) @ WithdrawalError::InvalidCollateral,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = user_profile.wallet == wallet.key() @ WithdrawalError::WalletMismatch,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    // constant 8 in space denotes the size of the discriminator
+    #[account(
+        init,
+        payer = wallet,
+        space = 8 + WithdrawalRequest::LEN,
+        seeds = [b"withdrawal_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", params.mint.as_ref()],
+        bump,
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: WithdrawalParams)]
+pub struct ExecuteWithdrawal<'info> {
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = params.mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = user_profile.wallet == wallet.key() @ WithdrawalError::WalletMismatch,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        mut,
+        seeds = [b"withdrawal_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", params.mint.as_ref()],
+        bump,
+        close = wallet,
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user_profile.wallet,
+    )]
+    pub user_usdc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    pub delegate: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: WithdrawalParams)]
+pub struct SettleWithdrawal<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    /// CHECK: Only receives the rent of the closed request, checked against the user profile
+    #[account(mut, address = user_profile.wallet)]
+    pub wallet: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"withdrawal_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", params.mint.as_ref()],
+        bump,
+        close = wallet,
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", params.mint.as_ref()],
+        bump,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOutcome<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(mut)]
+    pub event_data: Account<'info, EventData>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[error_code]
+pub enum UpdateOutcomeError {
+    #[msg("Trying to set outcome to Null, not allowed.")]
+    InvalidOutcomeState,
+    #[msg("You are only allowed to update once.")]
+    OutcomeAlreadyUpdated,
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct InitEventParams {
+    pub event_id: u64,
+    pub event_total_price: u64,
+    pub promo_policy: PromoPolicy,
+}
+
+/// How much of an order's lock may be paid with promo, and which balance is spent first.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
+pub enum PromoPolicy {
+    // Orders are paid with USDC only
+    Disallowed,
+    // Promo is spent first, up to the given bps of the order amount
+    CappedBps(u16),
+    #[default]
+    PromoFirst,
+    // USDC is spent first, promo only covers what the wallet can't
+    CashFirst,
+}
+
+impl PromoPolicy {
+    pub const MAX_CAP_BPS: u16 = 10_000;
+
+    pub fn is_valid(&self) -> bool {
+        match self {
+            PromoPolicy::CappedBps(bps) => *bps <= Self::MAX_CAP_BPS,
+            _ => true,
+        }
+    }
+
+    // Most promo this policy lets an order of `amount` spend
+    pub fn promo_limit(&self, amount: u64) -> u64 {
+        match self {
+            PromoPolicy::Disallowed => 0,
+            PromoPolicy::CappedBps(bps) => FeeConfig::apply_bps(amount, *bps as u64),
+            PromoPolicy::PromoFirst | PromoPolicy::CashFirst => amount,
+        }
+    }
+
+    /// Splits the lock of an order into (usdc, promo) under the event policy and the order's
+    /// override. The override can only lower the promo allowed, but decides which balance is
+    /// spent first. Returns `None` if the balances can't cover the amount under the policy.
+    pub fn split(
+        event_policy: PromoPolicy,
+        order_policy: Option<PromoPolicy>,
+        amount: u64,
+        promo_balance: u64,
+        usdc_balance: u64,
+    ) -> Option<(u64, u64)> {
+        let policy = order_policy.unwrap_or(event_policy);
+        let max_promo = event_policy
+            .promo_limit(amount)
+            .min(policy.promo_limit(amount))
+            .min(promo_balance);
+
+        let promo = match policy {
+            PromoPolicy::CashFirst => amount - usdc_balance.min(amount),
+            _ => max_promo,
+        };
+        if promo > max_promo {
+            return None;
+        }
+        Some((amount - promo, promo))
+    }
+}
+
+#[repr(u8)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, AnchorSerialize, AnchorDeserialize)]
+pub enum EventOutcome {
+    #[default]
+    Null = 0,
+    Yes,
+    No,
+    Void,
+}
+
+impl From<u8> for EventOutcome {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => EventOutcome::Null,
+            1 => EventOutcome::Yes,
+            2 => EventOutcome::No,
+            3 => EventOutcome::Void,
+            _ => EventOutcome::Null, // Default case if value doesn't match
+        }
+    }
+}
+
+#[account]
+pub struct EventData {
+    // Layout version, see `migrate_event_data` for how layouts are upgraded
+    pub version: u8,
+    pub event_id: u64,
+    pub outcome: EventOutcome,
+    pub is_outcome_set: bool,
+    pub event_total_price: u64,
+    pub promo_policy: PromoPolicy,
+    // Mint of the event escrow, every trade on the event settles in it
+    pub collateral_mint: Pubkey,
+    // Units held by all users on each side, the event can't be closed while any are left
+    pub outstanding_qty: [u64; 2],
+    // Set while the event has its own fee schedule, fills must then pass it
+    pub has_fee_config: bool,
+    // Set while the event has its own position limits, buys must then pass them
+    pub has_position_limits: bool,
+    pub reserved: [u8; 64],
+}
+
+impl EventData {
+    // Serialized size field by field, the promo policy at its largest variant
+    pub const LEN: usize = 1 + 8 + 1 + 1 + 8 + (1 + 2) + 32 + 2 * 8 + 1 + 1 + 64;
+    pub const VERSION: u8 = 1;
+
+    // Layout before versioning: event_id, outcome, is_outcome_set, event_total_price
+    pub const LEGACY_LEN: usize = 24;
+
+    pub fn from_legacy(
+        data: &[u8],
+        collateral_mint: Pubkey,
+        outstanding_qty: [u64; 2],
+    ) -> Result<EventData> {
+        if data.len() < 8 || data[..8] != EventData::DISCRIMINATOR {
+            return Err(MigrateEventDataError::InvalidAccount.into());
+        }
+        if data.len() != 8 + Self::LEGACY_LEN {
+            return Err(MigrateEventDataError::AlreadyMigrated.into());
+        }
+
+        let read_u64 =
+            |start: usize| u64::from_le_bytes(data[start..start + 8].try_into().unwrap());
+        Ok(EventData {
+            version: Self::VERSION,
+            event_id: read_u64(8),
+            outcome: EventOutcome::from(data[16]),
+            is_outcome_set: data[17] != 0,
+            event_total_price: read_u64(18),
+            promo_policy: PromoPolicy::default(),
+            // Legacy events all settled in USDC
+            collateral_mint,
+            // Positions were not counted on-chain, the owner passes them from the backend
+            outstanding_qty,
+            has_fee_config: false,
+            has_position_limits: false,
+            reserved: [0; 64],
+        })
+    }
+
+    // Upgrades an account of any older layout to the current one
+    pub fn migrate(
+        data: &[u8],
+        collateral_mint: Pubkey,
+        outstanding_qty: [u64; 2],
+    ) -> Result<EventData> {
+        if data.len() < 8 || data[..8] != EventData::DISCRIMINATOR {
+            return Err(MigrateEventDataError::InvalidAccount.into());
+        }
+        // Accounts from before versioning are told apart by their size
+        if data.len() == 8 + Self::LEGACY_LEN {
+            return Self::from_legacy(data, collateral_mint, outstanding_qty);
+        }
+        match data.get(8) {
+            Some(&Self::VERSION) => Err(MigrateEventDataError::AlreadyMigrated.into()),
+            _ => Err(MigrateEventDataError::UnsupportedVersion.into()),
+        }
+    }
+
+    // The schedule fills on this event pay, `None` when the event's own schedule was not passed
+    pub fn fee_config<'a>(
+        &self,
+        event_fee_config: Option<&'a FeeConfig>,
+        default: &'a FeeConfig,
+    ) -> Option<&'a FeeConfig> {
+        match event_fee_config {
+            Some(fee_config) => Some(fee_config),
+            None if self.has_fee_config => None,
+            None => Some(default),
+        }
+    }
+
+    pub fn add_outstanding(&mut self, order_type: OrderType, quantity: u64) {
+        self.outstanding_qty[order_type as usize] += quantity;
+    }
+
+    pub fn remove_outstanding(&mut self, order_type: OrderType, quantity: u64) {
+        // Units bought before the count was tracked were never added
+        let outstanding = &mut self.outstanding_qty[order_type as usize];
+        *outstanding = outstanding.saturating_sub(quantity);
+    }
+
+    pub fn check_closable(&self, escrow_amount: u64) -> Result<()> {
+        if !self.is_outcome_set {
+            msg!("Event event_id={:?} is not resolved", self.event_id);
+            return Err(CloseEventAccountError::EventNotResolved.into());
+        }
+        for order_type in OrderType::iterator() {
+            let qty = self.outstanding_qty[*order_type as usize];
+            if qty > 0 {
+                msg!("Outstanding qty={:?} order_type={:?}", qty, order_type);
+                return Err(CloseEventAccountError::OutstandingPositions.into());
+            }
+        }
+        if escrow_amount > 0 {
+            msg!("Event escrow still holds amount={:?}", escrow_amount);
+            return Err(CloseEventAccountError::EscrowNotEmpty.into());
+        }
+        Ok(())
+    }
+}
+
+#[error_code]
+pub enum InitializeEventError {
+    // No longer returned, commission moved to the fee config. Kept so the codes after it don't move.
+    #[msg("Trying to set commission rate > 100")]
+    InvalidCommissionRate,
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Promo cap must be at most 10000 bps.")]
+    InvalidPromoPolicy,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct EventPromoPolicyParams {
+    pub event_id: u64,
+    pub promo_policy: PromoPolicy,
+}
+
+#[derive(Accounts)]
+#[instruction(params: EventPromoPolicyParams)]
+pub struct SetEventPromoPolicy<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+}
+
+#[error_code]
+pub enum SetEventPromoPolicyError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Promo cap must be at most 10000 bps.")]
+    InvalidPromoPolicy,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct InitUserAtaParams {
+    pub user_id: u64,
+    pub promo_balance: u64,
+    pub wallet: Pubkey,
+    pub kyc_tier: u8,
+    // Ignored when no promo balance is granted
+    pub promo_campaign: PromoCampaign,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct PromoCampaign {
+    pub campaign_id: u64,
+    pub expires_at: i64,
+    // Buy volume the user has to trade before the promo can no longer expire
+    pub wagering_requirement: u64,
+}
+
+#[account]
+pub struct PromoGrant {
+    pub version: u8,
+    pub user_id: u64,
+    pub campaign_id: u64,
+    pub amount: u64,
+    // Expired or clawed back into the treasury, expiry also settles what was spent of the grant
+    pub returned: u64,
+    pub expires_at: i64,
+    pub wagering_requirement: u64,
+    // User's wagered volume when the promo was granted
+    pub wagered_at_grant: u64,
+    pub created_at: i64,
+}
+
+impl PromoGrant {
+    pub const LEN: usize = std::mem::size_of::<PromoGrant>();
+    pub const VERSION: u8 = 1;
+
+    // Creates the grant, or tops it up when the campaign already granted promo to the user
+    pub fn grant(
+        &mut self,
+        user_profile: &mut UserProfile,
+        campaign: &PromoCampaign,
+        amount: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        if campaign.expires_at <= now {
+            return Err(PromoGrantError::InvalidExpiry.into());
+        }
+
+        if self.created_at == 0 {
+            self.version = Self::VERSION;
+            self.user_id = user_profile.user_id;
+            self.campaign_id = campaign.campaign_id;
+            self.amount = 0;
+            self.returned = 0;
+            self.wagering_requirement = campaign.wagering_requirement;
+            self.wagered_at_grant = user_profile.wagered_volume;
+            self.created_at = now;
+        }
+
+        // A top-up never shortens the grant or lowers what has to be wagered for it
+        let add = |total: u64| {
+            total
+                .checked_add(amount)
+                .ok_or_else(|| error!(PromoGrantError::AmountOverflow))
+        };
+        self.amount = add(self.amount)?;
+        self.expires_at = self.expires_at.max(campaign.expires_at);
+        self.wagering_requirement = self.wagering_requirement.max(campaign.wagering_requirement);
+        user_profile.promo_granted = add(user_profile.promo_granted)?;
+        user_profile.promo_outstanding = add(user_profile.promo_outstanding)?;
+        user_profile.promo_expires_at = user_profile.promo_expires_at.max(self.expires_at);
+
+        emit!(PromoGranted {
+            user_id: self.user_id,
+            campaign_id: self.campaign_id,
+            amount,
+            expires_at: self.expires_at,
+            total_granted: user_profile.promo_granted,
+        });
+
+        msg!(
+            "Promo of {:?} granted to user_id={:?} under campaign_id={:?}, expires_at={:?}",
+            amount,
+            self.user_id,
+            self.campaign_id,
+            self.expires_at,
+        );
+
+        Ok(())
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.amount - self.returned
+    }
+
+    // This grant's part of the pooled promo balance. Spends are not recorded per grant, so the
+    // balance is split over the user's grants by what is left of each.
+    pub fn unspent(&self, promo_balance: u64, promo_outstanding: u64) -> u64 {
+        let remaining = self.remaining();
+        // Grants made before outstanding promo was tracked are not counted in it
+        let outstanding = promo_outstanding.max(remaining);
+        if outstanding == 0 {
+            return 0;
+        }
+        (promo_balance as u128 * remaining as u128 / outstanding as u128).min(remaining as u128)
+            as u64
+    }
+
+    // Books promo sent back to the treasury. An expired grant is settled in full, what was not
+    // returned had been spent before it expired.
+    pub fn record_return(
+        &mut self,
+        user_profile: &mut UserProfile,
+        amount: u64,
+        reason: PromoReturnReason,
+    ) {
+        let settled = match reason {
+            PromoReturnReason::Expired => self.remaining(),
+            PromoReturnReason::Clawback => amount,
+        };
+        self.returned += settled;
+        user_profile.promo_outstanding = user_profile.promo_outstanding.saturating_sub(settled);
+        if user_profile.promo_outstanding == 0 {
+            user_profile.promo_expires_at = 0;
+        }
+
+        emit!(PromoReturned {
+            user_id: self.user_id,
+            campaign_id: self.campaign_id,
+            amount,
+            reason,
+        });
+
+        msg!(
+            "Returned {:?} promo of campaign_id={:?} for user_id={:?}, reason={:?}",
+            amount,
+            self.campaign_id,
+            self.user_id,
+            reason,
+        );
+    }
+
+    pub fn wagering_met(&self, wagered_volume: u64) -> bool {
+        wagered_volume - self.wagered_at_grant >= self.wagering_requirement
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
+pub enum PromoReturnReason {
+    Expired = 0,
+    Clawback,
+}
+
+#[event]
+pub struct PromoGranted {
+    pub user_id: u64,
+    pub campaign_id: u64,
+    pub amount: u64,
+    pub expires_at: i64,
+    pub total_granted: u64,
+}
+
+#[event]
+pub struct PromoReturned {
+    pub user_id: u64,
+    pub campaign_id: u64,
+    pub amount: u64,
+    pub reason: PromoReturnReason,
+}
+
+#[error_code]
+pub enum PromoGrantError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Promo balance granted without a promo grant account.")]
+    MissingPromoGrant,
+    #[msg("Promo expiry must be in the future.")]
+    InvalidExpiry,
+    #[msg("Promo grant has not expired yet.")]
+    PromoNotExpired,
+    #[msg("User has completed the wagering requirement of this promo.")]
+    WageringCompleted,
+    #[msg("Amount exceeds what is left of the promo grant.")]
+    ExceedsPromoGrant,
+    #[msg("Promo amount must be greater than zero.")]
+    InvalidAmount,
+    #[msg("Grant would take the user over the promo cap.")]
+    PromoCapExceeded,
+    #[msg("Promo totals overflowed.")]
+    AmountOverflow,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct GrantPromoParams {
+    pub user_id: u64,
+    pub amount: u64,
+    pub promo_campaign: PromoCampaign,
+}
+
+#[derive(Accounts)]
+#[instruction(params: GrantPromoParams)]
+pub struct GrantPromo<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PromoGrant::LEN,
+        seeds = [b"promo_grant_uid_", params.user_id.to_le_bytes().as_ref(), b"_cid_", params.promo_campaign.campaign_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub promo_grant: Box<Account<'info, PromoGrant>>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        token::mint = config.usdc_mint,
+    )]
+    pub promo_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        address = config.arka_usdc_wallet,
+        token::mint = config.usdc_mint,
+    )]
+    pub arka_usdc_wallet: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(seeds = [b"money"], bump)]
+    pub arka_delegate: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct ExpirePromoParams {
+    pub user_id: u64,
+    pub campaign_id: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct ClawbackPromoParams {
+    pub user_id: u64,
+    pub campaign_id: u64,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: ExpirePromoParams)]
+pub struct ExpirePromo<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        mut,
+        seeds = [b"promo_grant_uid_", params.user_id.to_le_bytes().as_ref(), b"_cid_", params.campaign_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub promo_grant: Box<Account<'info, PromoGrant>>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        token::mint = config.usdc_mint,
+    )]
+    pub promo_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Returned promo goes back to the treasury registered in the config
+    #[account(
+        mut,
+        address = config.treasury,
+        token::mint = config.usdc_mint,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub promo_delegate: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: ClawbackPromoParams)]
+pub struct ClawbackPromo<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        mut,
+        seeds = [b"promo_grant_uid_", params.user_id.to_le_bytes().as_ref(), b"_cid_", params.campaign_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub promo_grant: Box<Account<'info, PromoGrant>>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        token::mint = config.usdc_mint,
+    )]
+    pub promo_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Returned promo goes back to the treasury registered in the config
+    #[account(
+        mut,
+        address = config.treasury,
+        token::mint = config.usdc_mint,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub promo_delegate: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct InitPromoAccount {
+    pub user_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitUserAtaParams)]
+pub struct InitializePromoAccount<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        payer = payer,
+        token::mint = mint,
+        token::authority = promo_delegate,
+    )]
+    pub promo_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub promo_delegate: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct InitUserEscrowParams {
+    pub user_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitUserEscrowParams)]
+pub struct InitializeUserEscrow<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(constraint = config.is_collateral(&mint.key()) @ InitializeUserAtaError::InvalidCollateral)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()], bump)]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        init,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        payer = payer,
+        token::mint = mint,
+        token::authority = delegate,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub delegate: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitUserEscrowParams)]
+pub struct MigrateUserEscrow<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub legacy_escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitUserAtaParams)]
+pub struct InitializeUserAta<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = config.usdc_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    // constant 8 in space denotes the size of the discriminator
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + UserProfile::LEN,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        init,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        payer = payer,
+        token::mint = mint,
+        token::authority = delegate,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        payer = payer,
+        token::mint = mint,
+        token::authority = promo_delegate,
+    )]
+    pub promo_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Only needed when a promo balance is granted
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PromoGrant::LEN,
+        seeds = [b"promo_grant_uid_", params.user_id.to_le_bytes().as_ref(), b"_cid_", params.promo_campaign.campaign_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub promo_grant: Option<Box<Account<'info, PromoGrant>>>,
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub promo_delegate: AccountInfo<'info>,
+    #[account(
+        mut,
+        address = config.arka_usdc_wallet,
+        token::mint = mint,
+    )]
+    pub arka_usdc_wallet: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(seeds = [b"money"], bump)]
+    pub arka_delegate: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct TranferFromUserWalletParams {
+    pub user_id: u64,
+    pub amount: u64,
+    pub event_id: u64,
+    pub order_id: u64,
+    pub order_type: OrderType,
+    pub price: u64,
+    pub quantity: u64,
+    pub intent_nonce: Option<u64>,
+    // The promo share of `amount` follows from this policy, it is not passed by the caller
+    pub promo_policy: Option<PromoPolicy>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: TranferFromUserWalletParams)]
+pub struct TranferFromUserWallet<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = event_data.collateral_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Box<Account<'info, EventData>>,
+    /// CHECK: Address is checked against the instructions sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user_profile.wallet,
+    )]
+    pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = mint.key() == config.usdc_mint @ TranferFromUserWalletError::PromoRequiresUsdc,
+        token::mint = mint,
+    )]
+    pub promo_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Created once per order_id so a retried lock fails instead of locking twice
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Order::LEN,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub order: Box<Account<'info, Order>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(seeds = [b"money"], bump)]
+    pub delegate: AccountInfo<'info>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the promo account
+    #[account(seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()], bump)]
+    pub promo_delegate: Option<AccountInfo<'info>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct TranferFromUserPdaParams {
+    pub user_id: u64,
+    pub amount: u64,
+    pub event_id: u64,
+    pub order_id: u64,
+    pub utr_id: String,
+    pub promo_amount: u64,
+    pub intent_nonce: Option<u64>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: TranferFromUserPdaParams)]
+pub struct TranferFromUserPda<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(constraint = config.is_collateral(&mint.key()) @ TranferFromUserPdaError::InvalidCollateral)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    /// CHECK: Address is checked against the instructions sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+    // Funds are only ever released to the wallet registered for this user_id
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user_profile.wallet,
+    )]
+    pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = mint.key() == config.usdc_mint @ TranferFromUserPdaError::PromoRequiresUsdc,
+        token::mint = mint,
+    )]
+    pub promo_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Created once per utr_id so a retried release fails instead of paying twice
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + ReleaseReceipt::LEN,
+        seeds = [b"utr_", hash(params.utr_id.as_bytes()).as_ref()],
+        bump,
+    )]
+    pub release_receipt: Box<Account<'info, ReleaseReceipt>>,
+    #[account(
+        mut,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = order.user_id == params.user_id @ TranferFromUserPdaError::OrderMismatch,
+        constraint = order.order_id == params.order_id @ TranferFromUserPdaError::OrderMismatch,
+        constraint = order.mint == mint.key() @ TranferFromUserPdaError::MintMismatch,
+    )]
+    pub order: Option<Box<Account<'info, Order>>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+#[instruction(params: InitEventParams)]
+pub struct InitializeEvent<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    // constant 8 in space denotes the size of the discriminator
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + EventData::LEN,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    // The collateral the event is denominated in, any of the accepted stablecoins
+    #[account(constraint = config.is_collateral(&mint.key()) @ InitializeEventError::InvalidCollateral)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+        payer = payer,
+        token::mint = mint,
+        token::authority = delegate,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub delegate: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+// Token initialization params
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct BuyOrderParams {
+    pub order_type: OrderType,
+    pub order_price: u64,
+    pub event_id: u64,
+    pub quantity: u64,
+    pub user_id: u64,
+    pub order_id: u64,
+    pub liquidity: Liquidity,
+}
+
+#[account]
+pub struct UserEventData {
+    pub version: u8,
+    pub avg_purchase_price: [u64; 2],
+    pub total_qty: [u64; 2],
+    // Commission rate in basis points per side, weighted by quantity like the purchase price
+    pub commission_bps: [u64; 2],
+    // Cumulative per side, so statements can be built from on-chain state alone
+    // Profit or loss of every sale against the average purchase price, before fees
+    pub realized_pnl: [i64; 2],
+    pub commission_paid: [u64; 2],
+    pub trading_fees_paid: [u64; 2],
+    // Notional of all buys and sells
+    pub volume: [u64; 2],
+    pub trade_count: [u64; 2],
+    // Exact total cost of the units held per side, the average price is derived from it
+    pub cost_basis: [u128; 2],
+    // Part of the units held, and of their cost, funded with promo per side
+    pub promo_qty: [u64; 2],
+    pub promo_principal: [u64; 2],
+    // Units per side promised to open sell orders, so the same units can't be offered twice
+    pub committed_qty: [u64; 2],
+    pub reserved: [u8; 48],
+}
+
+impl UserEventData {
+    // Serialized size field by field: the version, eleven [u64; 2] or [i64; 2] fields, the
+    // cost basis and the reserved bytes
+    pub const LEN: usize = 1 + 11 * 2 * 8 + 2 * 16 + 48;
+    pub const VERSION: u8 = 1;
+
+    // Layout before statement totals and cost basis: avg_purchase_price, total_qty, comission
+    pub const LEGACY_LEN: usize = 40;
+
+    pub fn from_legacy(data: &[u8]) -> Result<UserEventData> {
+        if data.len() < 8 || data[..8] != UserEventData::DISCRIMINATOR {
+            return Err(MigrateUserEventDataError::InvalidAccount.into());
+        }
+        if data.len() != 8 + Self::LEGACY_LEN {
+            return Err(MigrateUserEventDataError::AlreadyMigrated.into());
+        }
+
+        let mut fields = [0u64; 5];
+        for (i, field) in fields.iter_mut().enumerate() {
+            let start = 8 + i * 8;
+            *field = u64::from_le_bytes(data[start..start + 8].try_into().unwrap());
+        }
+        let [avg_yes, avg_no, qty_yes, qty_no, comission] = fields;
+
+        Ok(UserEventData {
+            version: Self::VERSION,
+            avg_purchase_price: [avg_yes, avg_no],
+            total_qty: [qty_yes, qty_no],
+            // The legacy rate was a percentage shared by both sides
+            commission_bps: [comission * 100; 2],
+            realized_pnl: [0; 2],
+            commission_paid: [0; 2],
+            trading_fees_paid: [0; 2],
+            volume: [0; 2],
+            trade_count: [0; 2],
+            cost_basis: [
+                avg_yes as u128 * qty_yes as u128,
+                avg_no as u128 * qty_no as u128,
+            ],
+            // Promo funding was not recorded, legacy positions are treated as cash
+            promo_qty: [0; 2],
+            promo_principal: [0; 2],
+            committed_qty: [0; 2],
+            reserved: [0; 48],
+        })
+    }
+
+    // Upgrades an account of any older layout to the current one
+    pub fn migrate(data: &[u8]) -> Result<UserEventData> {
+        if data.len() < 8 || data[..8] != UserEventData::DISCRIMINATOR {
+            return Err(MigrateUserEventDataError::InvalidAccount.into());
+        }
+        // Accounts from before versioning are told apart by their size
+        if data.len() == 8 + Self::LEGACY_LEN {
+            return Self::from_legacy(data);
+        }
+        match data.get(8) {
+            Some(&Self::VERSION) => Err(MigrateUserEventDataError::AlreadyMigrated.into()),
+            _ => Err(MigrateUserEventDataError::UnsupportedVersion.into()),
+        }
+    }
+
+    // Units of a side not yet promised to an open sell order
+    pub fn free_qty(&self, order_type: OrderType) -> u64 {
+        let side = order_type as usize;
+        self.total_qty[side].saturating_sub(self.committed_qty[side])
+    }
+
+    // Units held on both sides together
+    pub fn held_qty(&self) -> u64 {
+        self.total_qty[0].saturating_add(self.total_qty[1])
+    }
+
+    // Called when a sell order fills or is cancelled. Settlement after resolution ignores
+    // commitments, so there may be less left to release than the order promised.
+    pub fn release_committed(&mut self, order_type: OrderType, quantity: u64) {
+        let side = order_type as usize;
+        self.committed_qty[side] = self.committed_qty[side].saturating_sub(quantity);
+    }
+
+    // Cost of selling `quantity` units, the last unit sold takes whatever is left
+    pub fn cost_of(&self, order_type: OrderType, quantity: u64) -> u64 {
+        let side = order_type as usize;
+        if quantity >= self.total_qty[side] {
+            return self.cost_basis[side] as u64;
+        }
+        (self.cost_basis[side] * quantity as u128 / self.total_qty[side] as u128) as u64
+    }
+
+    // Promo principal and promo quantity of selling `quantity` units, the last unit sold takes
+    // whatever is left
+    pub fn promo_share(&self, order_type: OrderType, quantity: u64) -> (u64, u64) {
+        let side = order_type as usize;
+        if quantity >= self.total_qty[side] {
+            return (self.promo_principal[side], self.promo_qty[side]);
+        }
+
+        let share = |amount: u64| -> u64 {
+            (amount as u128 * quantity as u128 / self.total_qty[side] as u128) as u64
+        };
+        (
+            share(self.promo_principal[side]),
+            share(self.promo_qty[side]),
+        )
+    }
+
+    pub fn add_position(
+        &mut self,
+        order_type: OrderType,
+        price: u64,
+        quantity: u64,
+        commission_bps: u16,
+        trading_fee: u64,
+        promo_funded: u64,
+    ) -> Result<()> {
+        let side = order_type as usize;
+        let current_qty = self.total_qty[side] as u128;
+        let new_qty = current_qty + quantity as u128;
+        let notional = price.checked_mul(quantity).ok_or(PositionError::Overflow)?;
+
+        self.commission_bps[side] = ((self.commission_bps[side] as u128 * current_qty
+            + commission_bps as u128 * quantity as u128)
+            / new_qty) as u64;
+        self.cost_basis[side] = self.cost_basis[side]
+            .checked_add(notional as u128)
+            .ok_or(PositionError::Overflow)?;
+        self.total_qty[side] = self.total_qty[side]
+            .checked_add(quantity)
+            .ok_or(PositionError::Overflow)?;
+        self.avg_purchase_price[side] =
+            (self.cost_basis[side] / self.total_qty[side] as u128) as u64;
+
+        if notional > 0 {
+            self.promo_qty[side] = self.promo_qty[side]
+                .checked_add((quantity as u128 * promo_funded as u128 / notional as u128) as u64)
+                .ok_or(PositionError::Overflow)?;
+        }
+        self.promo_principal[side] = self.promo_principal[side]
+            .checked_add(promo_funded)
+            .ok_or(PositionError::Overflow)?;
+
+        self.trading_fees_paid[side] = self.trading_fees_paid[side]
+            .checked_add(trading_fee)
+            .ok_or(PositionError::Overflow)?;
+        self.volume[side] = self.volume[side]
+            .checked_add(notional)
+            .ok_or(PositionError::Overflow)?;
+        self.trade_count[side] = self.trade_count[side]
+            .checked_add(1)
+            .ok_or(PositionError::Overflow)?;
+        Ok(())
+    }
+
+    pub fn reduce_position(
+        &mut self,
+        order_type: OrderType,
+        price: u64,
+        quantity: u64,
+        commission: u64,
+        trading_fee: u64,
+    ) -> Result<()> {
+        let side = order_type as usize;
+        let proceeds = price.checked_mul(quantity).ok_or(PositionError::Overflow)?;
+        let cost = self.cost_of(order_type, quantity);
+        let (promo_principal, promo_qty) = self.promo_share(order_type, quantity);
+        let pnl = i64::try_from(proceeds)
+            .map_err(|_| error!(PositionError::Overflow))?
+            .checked_sub(i64::try_from(cost).map_err(|_| error!(PositionError::Overflow))?)
+            .ok_or(PositionError::Overflow)?;
+
+        self.cost_basis[side] = self.cost_basis[side]
+            .checked_sub(cost as u128)
+            .ok_or(PositionError::Overflow)?;
+        self.promo_principal[side] = self.promo_principal[side]
+            .checked_sub(promo_principal)
+            .ok_or(PositionError::Overflow)?;
+        self.promo_qty[side] = self.promo_qty[side]
+            .checked_sub(promo_qty)
+            .ok_or(PositionError::Overflow)?;
+        self.total_qty[side] = self.total_qty[side]
+            .checked_sub(quantity)
+            .ok_or(PositionError::Overflow)?;
+        self.realized_pnl[side] = self.realized_pnl[side]
+            .checked_add(pnl)
+            .ok_or(PositionError::Overflow)?;
+
+        self.commission_paid[side] = self.commission_paid[side]
+            .checked_add(commission)
+            .ok_or(PositionError::Overflow)?;
+        self.trading_fees_paid[side] = self.trading_fees_paid[side]
+            .checked_add(trading_fee)
+            .ok_or(PositionError::Overflow)?;
+        self.volume[side] = self.volume[side]
+            .checked_add(proceeds)
+            .ok_or(PositionError::Overflow)?;
+        self.trade_count[side] = self.trade_count[side]
+            .checked_add(1)
+            .ok_or(PositionError::Overflow)?;
+        Ok(())
+    }
+}
+
+#[error_code]
+pub enum PositionError {
+    #[msg("Position arithmetic overflowed.")]
+    Overflow,
+}
+
+#[error_code]
+pub enum MigrateUserEventDataError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Account is not a UserEventData account.")]
+    InvalidAccount,
+    #[msg("Account already uses the current layout.")]
+    AlreadyMigrated,
+    #[msg("Account layout version is not supported.")]
+    UnsupportedVersion,
+}
+
+// Grows a migrated account to `space`, topping up its rent from the payer, and writes it in the
+// current layout
+fn write_migrated<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+    migrated: &T,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    if rent > account.lamports() {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account.clone(),
+                },
+            ),
+            rent - account.lamports(),
+        )?;
+    }
+    account.realloc(space, false)?;
+    migrated.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])
+}
+
+#[error_code]
+pub enum MigrateEventDataError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Account is not an EventData account.")]
+    InvalidAccount,
+    #[msg("Account already uses the current layout.")]
+    AlreadyMigrated,
+    #[msg("Account layout version is not supported.")]
+    UnsupportedVersion,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
+pub struct MigrateEventDataParams {
+    pub event_id: u64,
+    // Units users hold on each side, legacy events did not count them
+    pub outstanding_qty: [u64; 2],
 }
 
 #[derive(Accounts)]
-#[instruction(params: TranferFromUserWalletParams)]
-pub struct TranferFromUserWallet<'info> {
+#[instruction(params: MigrateEventDataParams)]
+pub struct MigrateEventData<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
-    pub usdc_mint: Account<'info, OldMint>,
-    #[account(mut)]
-    pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
-    #[account(
-        mut,
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
-        bump,
-    )]
-    pub escrow_account: Box<Account<'info, OldTokenAccount>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    /// CHECK: Older layouts can not be deserialized, they are parsed by `EventData::migrate`
     #[account(
         mut,
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+        owner = crate::ID,
     )]
-    pub promo_account: Option<Box<Account<'info, OldTokenAccount>>>,
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    #[account(seeds = [b"money"], bump)]
-    pub delegate: AccountInfo<'info>,
-    /// CHECK: This account is safe as it is used to set the delegate authority for the promo account
-    #[account(seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()], bump)]
-    pub promo_delegate: Option<AccountInfo<'info>>,
+    pub event_data: UncheckedAccount<'info>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, OldToken>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct TranferFromUserPdaParams {
+pub struct MigrateUserEventDataParams {
     pub user_id: u64,
-    pub amount: u64,
     pub event_id: u64,
-    pub order_id: u64,
-    pub utr_id: String,
-    pub promo_amount: u64,
 }
 
 #[derive(Accounts)]
-#[instruction(params: TranferFromUserPdaParams)]
-pub struct TranferFromUserPda<'info> {
+#[instruction(params: MigrateUserEventDataParams)]
+pub struct MigrateUserEventData<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
-    pub usdc_mint: Account<'info, OldMint>,
-    #[account(mut)]
-    pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
-    #[account(
-        mut,
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
-        bump,
-    )]
-    pub escrow_account: Account<'info, OldTokenAccount>,
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    #[account(
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
-        bump,
-    )]
-    pub delegate: AccountInfo<'info>,
+    /// CHECK: Older layouts can not be deserialized, they are parsed by `UserEventData::migrate`
     #[account(
         mut,
-        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        seeds = [b"uid_", params.user_id.to_le_bytes().as_ref(), b"_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+        owner = crate::ID,
     )]
-    pub promo_account: Option<Box<Account<'info, OldTokenAccount>>>,
+    pub user_event_data: UncheckedAccount<'info>,
     #[account(mut)]
     pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, OldToken>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 #[derive(Accounts)]
-#[instruction(params: InitEventParams)]
-pub struct InitializeEvent<'info> {
+#[instruction(params: BuyOrderParams)]
+pub struct BuyOrder<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
-    // constant 8 in space denotes the size of the discriminator
     #[account(
-        init,
+        init_if_needed,
+        seeds = [b"uid_", params.user_id.to_le_bytes().as_ref(), b"_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
         payer = payer,
-        space = 8 + EventData::LEN,
-        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        space = 8 + UserEventData::LEN,
+    )]
+    pub user_arka_event_account: Account<'info, UserEventData>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = event_data.collateral_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
         bump,
     )]
-    pub event_data: Account<'info, EventData>,
-    pub usdc_mint: Account<'info, OldMint>,
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(seeds = [b"fee_config"], bump)]
+    pub fee_config: Box<Account<'info, FeeConfig>>,
+    // Only passed when the event overrides the default fee schedule
     #[account(
-        init,
-        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        seeds = [b"fee_config_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
-        payer = payer,
-        token::mint = usdc_mint,
-        token::authority = delegate,
     )]
-    pub escrow_account: Account<'info, OldTokenAccount>,
+    pub event_fee_config: Option<Box<Account<'info, FeeConfig>>>,
+    // Must be passed when the event has its own position limits
     #[account(
+        seeds = [b"limits_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_position_limits: Option<Box<Account<'info, EventPositionLimits>>>,
+    #[account(
+        mut,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = order.user_id == params.user_id @ BuyOrderError::OrderMismatch,
+    )]
+    pub order: Box<Account<'info, Order>>,
+    // Fees are collected into the program-owned fee vault
+    #[account(
+        mut,
+        seeds = [b"fees", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, seeds = [b"fee_ledger", mint.key().as_ref()], bump)]
+    pub fee_ledger: Box<Account<'info, FeeLedger>>,
+    // Only needed when the user has a referrer
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = user_profile.referrer == Some(referrer_token_account.owner) @ BuyOrderError::MissingReferrerAccount,
+    )]
+    pub referrer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub user_usdc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
         seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+        token::mint = mint,
     )]
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
-    pub delegate: AccountInfo<'info>,
+    pub arka_usdc_event_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(mut)]
     pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, OldToken>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    #[account(
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+}
+
+#[error_code]
+pub enum PlaceSellOrderError {
+    #[msg("Price > 100")]
+    InvalidPrice,
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("User account is frozen.")]
+    UserFrozen,
+    #[msg("User does not hold enough quantity to sell.")]
+    InsufficientPosition,
 }
 
-// Token initialization params
 #[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone)]
-pub struct BuyOrderParams {
-    pub order_type: OrderType,
-    pub order_price: u64,
+pub struct PlaceSellOrderParams {
+    pub order_id: u64,
+    pub user_id: u64,
     pub event_id: u64,
+    pub order_type: OrderType,
+    pub price: u64,
     pub quantity: u64,
-    pub user_id: u64,
-    pub commission: u64,
-}
-
-#[account]
-pub struct UserEventData {
-    pub avg_purchase_price: [u64; 2],
-    pub total_qty: [u64; 2],
-    pub comission: u64,
-}
-
-impl UserEventData {
-    pub const LEN: usize = std::mem::size_of::<UserEventData>();
+    pub intent_nonce: Option<u64>,
 }
 
 #[derive(Accounts)]
-#[instruction(params: BuyOrderParams)]
-pub struct BuyOrder<'info> {
+#[instruction(params: PlaceSellOrderParams)]
+pub struct PlaceSellOrder<'info> {
     /// CHECK: This account is safe since this is our owner account.
     #[account(signer)]
     pub owner: Signer<'info>,
     #[account(
-        init_if_needed,
-        seeds = [b"uid_", params.user_id.to_le_bytes().as_ref(), b"_eid_", params.event_id.to_le_bytes().as_ref()],
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
         bump,
-        payer = payer,
-        space = 8 + UserEventData::LEN,
     )]
-    pub user_arka_event_account: Account<'info, UserEventData>,
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    /// CHECK: Address is checked against the instructions sysvar id
+    #[account(address = sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
     #[account(
         mut,
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        seeds = [b"uid_", params.user_id.to_le_bytes().as_ref(), b"_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
     )]
-    pub user_usdc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub user_arka_event_account: Box<Account<'info, UserEventData>>,
     #[account(
-        mut,
-        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
     )]
-    pub arka_usdc_event_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    pub rent: Sysvar<'info, Rent>,
-    pub system_program: Program<'info, System>,
-    pub old_token_program: Program<'info, OldToken>,
-    /// CHECK: This account is safe as it is used to set the delegate authority for the token account
+    pub event_data: Box<Account<'info, EventData>>,
+    // Shares the order_id namespace with buy orders
     #[account(
-        seeds = [b"usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        init,
+        payer = payer,
+        space = 8 + Order::LEN,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
         bump,
     )]
-    pub delegate: AccountInfo<'info>,
-    pub event_data: Account<'info, EventData>,
+    pub order: Box<Account<'info, Order>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 // Token initialization params
@@ -1034,7 +5419,8 @@ pub struct SellOrderParams {
     pub quantity: u64,
     pub user_id: u64,
     pub selling_price: u64,
-    pub promo_amount: u64,
+    pub order_id: u64,
+    pub liquidity: Liquidity,
 }
 
 #[derive(Accounts)]
@@ -1049,33 +5435,87 @@ pub struct SellOrder<'info> {
         bump,
     )]
     pub user_arka_event_account: Account<'info, UserEventData>,
-    #[account(mut)]
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(address = event_data.collateral_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(seeds = [b"fee_config"], bump)]
+    pub fee_config: Box<Account<'info, FeeConfig>>,
+    // Only passed when the event overrides the default fee schedule
+    #[account(
+        seeds = [b"fee_config_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_fee_config: Option<Box<Account<'info, FeeConfig>>>,
+    // Only needed when the user has a referrer
+    #[account(
+        mut,
+        token::mint = mint,
+        constraint = user_profile.referrer == Some(referrer_token_account.owner) @ SellOrderError::MissingReferrerAccount,
+    )]
+    pub referrer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    // Not needed when settling a resolved event
+    #[account(
+        mut,
+        seeds = [b"oid_", params.order_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = order.user_id == params.user_id @ SellOrderError::OrderMismatch,
+    )]
+    pub order: Option<Box<Account<'info, Order>>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
     pub user_usdc_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         mut,
         seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
+        token::mint = mint,
     )]
     pub arka_usdc_event_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
         bump,
+        constraint = mint.key() == config.usdc_mint @ SellOrderError::PromoRequiresUsdc,
+        token::mint = mint,
     )]
     pub promo_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
-    #[account(mut)]
-    pub arka_usdc_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Fees are collected into the program-owned fee vault
+    #[account(
+        mut,
+        seeds = [b"fees", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub fee_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, seeds = [b"fee_ledger", mint.key().as_ref()], bump)]
+    pub fee_ledger: Box<Account<'info, FeeLedger>>,
     #[account(mut, signer)]
     pub payer: Signer<'info>,
     pub rent: Sysvar<'info, Rent>,
     pub system_program: Program<'info, System>,
-    pub old_token_program: Program<'info, OldToken>,
+    pub token_program: Interface<'info, TokenInterface>,
     /// CHECK: This account is safe as it is used to set the delegate authority for the token account
     #[account(
         seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
         bump,
     )]
     pub delegate: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
     pub event_data: Account<'info, EventData>,
 }
 
@@ -1097,6 +5537,12 @@ pub struct CloseEventAccount<'info> {
         close = payer,
     )]
     pub event_data: Account<'info, EventData>,
+    #[account(
+        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: The event escrow, either already closed or checked to be empty
+    pub escrow_account: UncheckedAccount<'info>,
     #[account(signer)]
     pub payer: Signer<'info>,
 }
@@ -1131,3 +5577,134 @@ pub struct CloseUserEventAccount<'info> {
     #[account(signer)]
     pub payer: Signer<'info>,
 }
+
+#[error_code]
+pub enum CloseUserAccountsError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Mint is not an accepted collateral.")]
+    InvalidCollateral,
+    #[msg("Treasury account is required to sweep the promo account.")]
+    MissingTreasury,
+    #[msg("Promo is only held in USDC, it can't be used with another mint.")]
+    PromoRequiresUsdc,
+    #[msg("User still has open orders holding funds in the escrow.")]
+    OrdersStillOpen,
+    #[msg("User has a pending withdrawal for this mint.")]
+    WithdrawalPending,
+    #[msg("User still has promo grants that have not expired.")]
+    PromoNotExpired,
+    #[msg("User still holds positions.")]
+    PositionsStillOpen,
+    #[msg("User still has resting sell orders.")]
+    SellOrdersStillOpen,
+    #[msg("Every promo grant still outstanding must be passed, once each.")]
+    MissingPromoGrants,
+    #[msg("User has completed the wagering of a promo grant, it is theirs to keep.")]
+    WageringCompleted,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, serde::Deserialize)]
+pub struct CloseUserAccountsParams {
+    pub user_id: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseUserAccountsParams)]
+pub struct CloseUserAccounts<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Box<Account<'info, Config>>,
+    #[account(constraint = config.is_collateral(&mint.key()) @ CloseUserAccountsError::InvalidCollateral)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"profile_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub user_profile: Box<Account<'info, UserProfile>>,
+    #[account(
+        mut,
+        seeds = [b"escrow_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    // Receives the escrow dust
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = user_profile.wallet,
+    )]
+    pub user_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"withdrawal_uid_", params.user_id.to_le_bytes().as_ref(), b"_mint_", mint.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: The user's withdrawal request for this mint, checked not to exist
+    pub withdrawal_request: UncheckedAccount<'info>,
+    // Only for USDC, the promo account is closed with the USDC escrow
+    #[account(
+        mut,
+        seeds = [b"promo_usdc_uid_", params.user_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = mint.key() == config.usdc_mint @ CloseUserAccountsError::PromoRequiresUsdc,
+        token::mint = mint,
+    )]
+    pub promo_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        address = config.treasury,
+        token::mint = mint,
+    )]
+    pub treasury: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[error_code]
+pub enum CloseEventEscrowError {
+    #[msg("Unauthorized: Only the owner can execute this instruction.")]
+    Unauthorized,
+    #[msg("Event escrow can only be closed once the event is resolved.")]
+    EventNotResolved,
+    #[msg("Users still hold positions in the event.")]
+    OutstandingPositions,
+}
+
+#[derive(Accounts)]
+#[instruction(params: CloseEventAccountParams)]
+pub struct CloseEventEscrow<'info> {
+    /// CHECK: This account is safe since this is our owner account.
+    #[account(signer)]
+    pub owner: Signer<'info>,
+    #[account(
+        seeds = [b"eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub event_data: Account<'info, EventData>,
+    #[account(address = event_data.collateral_mint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [b"usdc_eid_", params.event_id.to_le_bytes().as_ref()],
+        bump,
+        token::mint = mint,
+    )]
+    pub escrow_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(seeds = [b"fee_ledger", mint.key().as_ref()], bump)]
+    pub fee_ledger: Box<Account<'info, FeeLedger>>,
+    // Dust goes to the treasury of the event's collateral
+    #[account(
+        mut,
+        address = fee_ledger.treasury,
+        token::mint = mint,
+    )]
+    pub treasury: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
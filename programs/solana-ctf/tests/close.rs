@@ -0,0 +1,33 @@
+mod common;
+
+use common::fixtures::event;
+use solana_ctf::{EventOutcome, OrderType};
+
+#[test]
+fn test_outstanding_qty_follows_fills() {
+    let mut event = event();
+
+    event.add_outstanding(OrderType::Yes, 5);
+    event.add_outstanding(OrderType::No, 3);
+    event.remove_outstanding(OrderType::Yes, 2);
+    assert_eq!(event.outstanding_qty, [3, 3]);
+
+    // Units bought before tracking started can't push the count below zero
+    event.remove_outstanding(OrderType::No, 10);
+    assert_eq!(event.outstanding_qty, [3, 0]);
+}
+
+#[test]
+fn test_event_closable_only_when_settled() {
+    let mut event = event();
+    event.add_outstanding(OrderType::Yes, 1);
+    assert!(event.check_closable(0).is_err());
+
+    event.is_outcome_set = true;
+    event.outcome = EventOutcome::Yes;
+    assert!(event.check_closable(0).is_err());
+
+    event.remove_outstanding(OrderType::Yes, 1);
+    assert!(event.check_closable(500).is_err());
+    assert!(event.check_closable(0).is_ok());
+}
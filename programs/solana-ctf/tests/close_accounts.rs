@@ -0,0 +1,109 @@
+mod common;
+
+use common::{
+    assert_error, buy_params, lock_params, place_sell_params, sell_params, TestEnv, ONE_DOLLAR,
+};
+use solana_ctf::{CloseUserAccountsError, PromoGrant, PromoPolicy, UserProfile};
+use solana_program_test::tokio;
+
+#[tokio::test]
+async fn test_user_accounts_close_only_once_idle() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    env.grant_promo(
+        &user,
+        2,
+        ONE_DOLLAR,
+        7 * 24 * 60 * 60 + 100,
+        100 * ONE_DOLLAR,
+    )
+    .await;
+
+    // Funds of an open order are still in the escrow
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+    assert_error(
+        env.close_user_accounts(&user, None).await,
+        CloseUserAccountsError::OrdersStillOpen,
+    );
+    env.cancel_order(&user, 1).await.unwrap();
+
+    // A pending withdrawal would pay out of the closed escrow
+    env.request_withdrawal(&user).await.unwrap();
+    assert_error(
+        env.close_user_accounts(&user, None).await,
+        CloseUserAccountsError::WithdrawalPending,
+    );
+    env.advance_clock(7 * 24 * 60 * 60).await;
+    env.execute_withdrawal(&user).await.unwrap();
+
+    // The grant still has a little while to run
+    assert_error(
+        env.close_user_accounts(&user, Some(&[2])).await,
+        CloseUserAccountsError::PromoNotExpired,
+    );
+    env.advance_clock(200).await;
+    env.close_user_accounts(&user, Some(&[2])).await.unwrap();
+
+    let treasury = env.treasury;
+    assert_eq!(env.balance(&treasury).await, ONE_DOLLAR);
+    assert!(!env.exists(&env.escrow(1)).await);
+    assert!(!env.exists(&env.promo_account(1)).await);
+}
+
+#[tokio::test]
+async fn test_close_waits_for_positions_and_expires_each_grant() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    env.grant_promo(&user, 2, ONE_DOLLAR, 100, 100 * ONE_DOLLAR)
+        .await;
+    env.grant_promo(&user, 3, 2 * ONE_DOLLAR, 200, 100 * ONE_DOLLAR)
+        .await;
+
+    // Units held or offered for sale would be settled into the closed escrow
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 3)).await;
+    env.send(&[buy], &[]).await.unwrap();
+    let place = env.place_sell_ix(&user, place_sell_params(1, 1, 2, 300000, 3));
+    env.send(&[place], &[]).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!((profile.open_positions, profile.open_sell_orders), (1, 1));
+    assert_error(
+        env.close_user_accounts(&user, None).await,
+        CloseUserAccountsError::PositionsStillOpen,
+    );
+
+    env.cancel_order(&user, 2).await.unwrap();
+    let place = env.place_sell_ix(&user, place_sell_params(1, 1, 3, 300000, 3));
+    env.send(&[place], &[]).await.unwrap();
+    let sell = env.sell_ix(&user, sell_params(1, 1, 3, 300000, 3)).await;
+    env.send(&[sell], &[]).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!((profile.open_positions, profile.open_sell_orders), (0, 0));
+
+    // Every outstanding grant is expired on the way, none can be left out
+    env.advance_clock(300).await;
+    assert_error(
+        env.close_user_accounts(&user, Some(&[2])).await,
+        CloseUserAccountsError::MissingPromoGrants,
+    );
+    assert_error(
+        env.close_user_accounts(&user, Some(&[2, 2])).await,
+        CloseUserAccountsError::MissingPromoGrants,
+    );
+    env.close_user_accounts(&user, Some(&[2, 3])).await.unwrap();
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.promo_outstanding, 0);
+    assert_eq!(profile.promo_expires_at, 0);
+    for (campaign_id, amount) in [(2, ONE_DOLLAR), (3, 2 * ONE_DOLLAR)] {
+        let grant: PromoGrant = env.account(&env.promo_grant(1, campaign_id)).await;
+        assert_eq!(grant.returned, amount);
+    }
+    let treasury = env.treasury;
+    assert_eq!(env.balance(&treasury).await, 3 * ONE_DOLLAR);
+    assert!(!env.exists(&env.promo_account(1)).await);
+}
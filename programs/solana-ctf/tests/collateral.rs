@@ -0,0 +1,51 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::fixtures::config;
+use common::{assert_error, TestEnv};
+use solana_ctf::{InitializeEventError, PromoPolicy, SetEventPromoPolicyError};
+use solana_program_test::tokio;
+
+#[test]
+fn test_usdc_is_always_collateral() {
+    let usdc = Pubkey::new_unique();
+    let config = config(usdc);
+
+    assert!(config.is_collateral(&usdc));
+    assert!(!config.is_collateral(&Pubkey::new_unique()));
+}
+
+#[test]
+fn test_whitelisted_mints_are_collateral() {
+    let usdt = Pubkey::new_unique();
+    let mut config = config(Pubkey::new_unique());
+    config.collateral_mints[1] = usdt;
+
+    assert!(config.is_collateral(&usdt));
+    // Unused slots never match the default pubkey
+    assert!(!config.is_collateral(&Pubkey::default()));
+}
+
+#[tokio::test]
+async fn test_promo_is_only_allowed_on_usdc_events() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    let other = env.add_collateral().await;
+
+    // Promo accounts only hold USDC
+    let init = env.initialize_event_ix(2, other, PromoPolicy::PromoFirst);
+    assert_error(
+        env.send(&[init], &[]).await,
+        InitializeEventError::PromoRequiresUsdc,
+    );
+    let init = env.initialize_event_ix(2, other, PromoPolicy::Disallowed);
+    env.send(&[init], &[]).await.unwrap();
+    assert_error(
+        env.set_event_promo_policy(2, PromoPolicy::CashFirst).await,
+        SetEventPromoPolicyError::PromoRequiresUsdc,
+    );
+
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    env.set_event_promo_policy(1, PromoPolicy::CashFirst)
+        .await
+        .unwrap();
+}
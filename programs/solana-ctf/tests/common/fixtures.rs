@@ -0,0 +1,151 @@
+//! Account values for tests that call the program's helpers directly.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Discriminator;
+use solana_ctf::{
+    Config, EventData, EventOutcome, FeeConfig, Order, OrderKind, OrderStatus, OrderType,
+    PositionLimits, PromoGrant, PromoPolicy, UserEventData, UserProfile,
+};
+
+pub fn config(usdc_mint: Pubkey) -> Config {
+    Config {
+        version: Config::VERSION,
+        usdc_mint,
+        treasury: Pubkey::new_unique(),
+        arka_usdc_wallet: Pubkey::new_unique(),
+        withdrawal_delay: 0,
+        fee_authority: Pubkey::new_unique(),
+        position_limits: PositionLimits::default(),
+        max_promo_per_user: 0,
+        collateral_mints: [Pubkey::default(); Config::MAX_COLLATERALS],
+        reserved: [0; 64],
+    }
+}
+
+pub fn user_profile(promo_granted: u64) -> UserProfile {
+    UserProfile {
+        version: UserProfile::VERSION,
+        user_id: 1,
+        wallet: Pubkey::new_unique(),
+        created_at: 0,
+        kyc_tier: 0,
+        status: 0,
+        intent_nonce: 0,
+        referrer: None,
+        wagered_volume: 0,
+        promo_granted,
+        open_orders: 0,
+        promo_outstanding: promo_granted,
+        promo_expires_at: 0,
+        open_positions: 0,
+        open_sell_orders: 0,
+        reserved: [0; 24],
+    }
+}
+
+pub fn fee_config(tier_commission_bps: [u16; FeeConfig::TIERS]) -> FeeConfig {
+    FeeConfig {
+        version: FeeConfig::VERSION,
+        tier_commission_bps,
+        maker_fee_bps: 0,
+        taker_fee_bps: 0,
+        referral_share_bps: 0,
+    }
+}
+
+pub fn event() -> EventData {
+    EventData {
+        version: EventData::VERSION,
+        event_id: 1,
+        outcome: EventOutcome::Null,
+        is_outcome_set: false,
+        event_total_price: 1000000,
+        promo_policy: PromoPolicy::default(),
+        collateral_mint: Pubkey::new_unique(),
+        outstanding_qty: [0; 2],
+        has_fee_config: false,
+        has_position_limits: false,
+        reserved: [0; 64],
+    }
+}
+
+pub fn position(total_qty: [u64; 2], cost_basis: [u128; 2]) -> UserEventData {
+    UserEventData {
+        version: UserEventData::VERSION,
+        avg_purchase_price: [0; 2],
+        total_qty,
+        commission_bps: [0; 2],
+        realized_pnl: [0; 2],
+        commission_paid: [0; 2],
+        trading_fees_paid: [0; 2],
+        volume: [0; 2],
+        trade_count: [0; 2],
+        cost_basis,
+        promo_qty: [0; 2],
+        promo_principal: [0; 2],
+        committed_qty: [0; 2],
+        reserved: [0; 48],
+    }
+}
+
+pub fn empty_position() -> UserEventData {
+    position([0; 2], [0; 2])
+}
+
+/// A buy order of 3 units at 0.3 with nothing filled or refunded yet.
+pub fn locked_order(usdc_amount: u64, promo_amount: u64) -> Order {
+    Order {
+        version: Order::VERSION,
+        order_id: 1,
+        user_id: 1,
+        event_id: 1,
+        mint: Pubkey::default(),
+        kind: OrderKind::Buy,
+        order_type: OrderType::Yes,
+        price: 300000,
+        quantity: 3,
+        filled_qty: 0,
+        usdc_amount,
+        promo_amount,
+        filled_amount: 0,
+        refunded_amount: 0,
+        refunded_promo: 0,
+        status: OrderStatus::Open,
+        created_at: 0,
+    }
+}
+
+pub fn grant(amount: u64, wagering_requirement: u64, wagered_at_grant: u64) -> PromoGrant {
+    PromoGrant {
+        version: PromoGrant::VERSION,
+        user_id: 1,
+        campaign_id: 7,
+        amount,
+        returned: 0,
+        expires_at: 0,
+        wagering_requirement,
+        wagered_at_grant,
+        created_at: 0,
+    }
+}
+
+/// A `UserEventData` in the layout from before versioning.
+pub fn legacy_position(avg: [u64; 2], qty: [u64; 2], comission: u64) -> Vec<u8> {
+    let mut data = UserEventData::DISCRIMINATOR.to_vec();
+    for field in [avg[0], avg[1], qty[0], qty[1], comission] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data
+}
+
+/// An `EventData` in the layout from before versioning.
+pub fn legacy_event(event_id: u64, outcome: u8, is_outcome_set: bool, total_price: u64) -> Vec<u8> {
+    let mut data = EventData::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&event_id.to_le_bytes());
+    data.push(outcome);
+    data.push(is_outcome_set as u8);
+    data.extend_from_slice(&total_price.to_le_bytes());
+    // Accounts were sized with `size_of`, leaving alignment padding after the fields
+    data.resize(8 + EventData::LEGACY_LEN, 0);
+    data
+}
@@ -0,0 +1,1193 @@
+#![allow(dead_code)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{Instruction, InstructionError};
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::InstructionData;
+use solana_program_test::*;
+use solana_sdk::{
+    account::AccountSharedData,
+    signature::{Keypair, Signer},
+    system_instruction, system_program,
+    sysvar::rent::ID as SYSVAR_RENT_PUBKEY,
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+use spl_token_2022::extension::{transfer_fee, ExtensionType, StateWithExtensions};
+use spl_token_2022::state::{Account as TokenAccountState, Mint as MintState};
+
+pub mod fixtures;
+
+pub const ONE_DOLLAR: u64 = 1_000_000;
+pub const DECIMALS: u8 = 6;
+
+// The owner key is held off-chain, so the program is run natively with the owner
+// account marked as a signer. None of our CPIs need the owner's signature.
+fn process(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts: Vec<AccountInfo> = accounts
+        .iter()
+        .map(|account| {
+            let mut account = account.clone();
+            if *account.key == solana_ctf::OWNER {
+                account.is_signer = true;
+            }
+            account
+        })
+        .collect();
+    let accounts: &[AccountInfo] = Box::leak(accounts.into_boxed_slice());
+    solana_ctf::entry(program_id, accounts, data)
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &solana_ctf::ID).0
+}
+
+pub fn ix(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    let mut metas = accounts.to_account_metas(None);
+    for meta in metas.iter_mut() {
+        if meta.pubkey == solana_ctf::OWNER {
+            meta.is_signer = false;
+        }
+    }
+    Instruction {
+        program_id: solana_ctf::ID,
+        accounts: metas,
+        data: data.data(),
+    }
+}
+
+/// Leaves an optional account out of `ix`, the way a client omits it.
+pub fn omit(mut ix: Instruction, account: &Pubkey) -> Instruction {
+    for meta in ix
+        .accounts
+        .iter_mut()
+        .filter(|meta| meta.pubkey == *account)
+    {
+        *meta = AccountMeta::new_readonly(solana_ctf::ID, false);
+    }
+    ix
+}
+
+/// Asserts the transaction failed with the given program error.
+pub fn assert_error(result: std::result::Result<(), BanksClientError>, error: impl Into<u32>) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, error.into())
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+pub struct TestUser {
+    pub user_id: u64,
+    pub wallet: Keypair,
+    pub token_account: Pubkey,
+}
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+    pub token_program: Pubkey,
+    pub mint: Pubkey,
+    pub mint_authority: Keypair,
+    pub treasury: Pubkey,
+    pub arka_usdc_wallet: Pubkey,
+}
+
+impl TestEnv {
+    /// Starts a bank with the config initialized for a fresh mint of `token_program`. A
+    /// non-zero `transfer_fee_bps` gives the mint the Token-2022 transfer fee extension.
+    pub async fn start(token_program: Pubkey, transfer_fee_bps: u16) -> TestEnv {
+        let program = ProgramTest::new("solana_ctf", solana_ctf::ID, processor!(process));
+        let context = program.start_with_context().await;
+
+        let mint = Keypair::new();
+        let mut env = TestEnv {
+            context,
+            token_program,
+            mint: mint.pubkey(),
+            mint_authority: Keypair::new(),
+            treasury: Pubkey::default(),
+            arka_usdc_wallet: Pubkey::default(),
+        };
+        env.create_mint(&mint, transfer_fee_bps).await;
+
+        env.treasury = env.create_token_account(&Pubkey::new_unique()).await;
+        let arka = Keypair::new();
+        env.arka_usdc_wallet = env.create_token_account(&arka.pubkey()).await;
+        env.mint_to(&env.arka_usdc_wallet.clone(), 1_000 * ONE_DOLLAR)
+            .await;
+        env.approve(&arka, &env.arka_usdc_wallet.clone(), u64::MAX)
+            .await;
+
+        env.initialize_config().await;
+        env.set_fee_config(fee_params(0, 0)).await;
+        env.initialize_fee_vault().await;
+        env
+    }
+
+    pub async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), BanksClientError> {
+        let blockhash = self
+            .context
+            .banks_client
+            .get_latest_blockhash()
+            .await
+            .unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context.banks_client.process_transaction(tx).await
+    }
+
+    async fn create_mint(&mut self, mint: &Keypair, transfer_fee_bps: u16) {
+        let payer = self.context.payer.pubkey();
+        let extensions = if transfer_fee_bps > 0 {
+            vec![ExtensionType::TransferFeeConfig]
+        } else {
+            vec![]
+        };
+        let space = ExtensionType::try_calculate_account_len::<MintState>(&extensions).unwrap();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+
+        let mut ixs = vec![system_instruction::create_account(
+            &payer,
+            &mint.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            &self.token_program,
+        )];
+        if transfer_fee_bps > 0 {
+            ixs.push(
+                transfer_fee::instruction::initialize_transfer_fee_config(
+                    &self.token_program,
+                    &mint.pubkey(),
+                    None,
+                    None,
+                    transfer_fee_bps,
+                    u64::MAX,
+                )
+                .unwrap(),
+            );
+        }
+        ixs.push(
+            spl_token_2022::instruction::initialize_mint(
+                &self.token_program,
+                &mint.pubkey(),
+                &self.mint_authority.pubkey(),
+                None,
+                DECIMALS,
+            )
+            .unwrap(),
+        );
+        self.send(&ixs, &[mint]).await.unwrap();
+    }
+
+    // Wallets pay the rent of the accounts they open themselves
+    pub async fn airdrop(&mut self, wallet: &Pubkey) {
+        let ix = system_instruction::transfer(&self.context.payer.pubkey(), wallet, 1_000_000_000);
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    pub async fn create_token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let ix = spl_associated_token_account::instruction::create_associated_token_account(
+            &self.context.payer.pubkey(),
+            owner,
+            &self.mint,
+            &self.token_program,
+        );
+        self.send(&[ix], &[]).await.unwrap();
+        get_associated_token_address_with_program_id(owner, &self.mint, &self.token_program)
+    }
+
+    pub async fn mint_to(&mut self, account: &Pubkey, amount: u64) {
+        let ix = spl_token_2022::instruction::mint_to(
+            &self.token_program,
+            &self.mint,
+            account,
+            &self.mint_authority.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        let mint_authority = self.mint_authority.insecure_clone();
+        self.send(&[ix], &[&mint_authority]).await.unwrap();
+    }
+
+    // Lets the `money` delegate move funds out of `account`
+    pub async fn approve(&mut self, owner: &Keypair, account: &Pubkey, amount: u64) {
+        let ix = spl_token_2022::instruction::approve(
+            &self.token_program,
+            account,
+            &pda(&[b"money"]),
+            &owner.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.send(&[ix], &[owner]).await.unwrap();
+    }
+
+    pub async fn balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*account)
+            .await
+            .unwrap()
+            .expect("token account not found");
+        StateWithExtensions::<TokenAccountState>::unpack(&account.data)
+            .unwrap()
+            .base
+            .amount
+    }
+
+    pub async fn account<T: AccountDeserialize>(&mut self, address: &Pubkey) -> T {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .expect("account not found");
+        T::try_deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    pub async fn exists(&mut self, address: &Pubkey) -> bool {
+        self.context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .is_some()
+    }
+
+    pub fn config(&self) -> Pubkey {
+        pda(&[b"config"])
+    }
+
+    pub fn user_profile(&self, user_id: u64) -> Pubkey {
+        pda(&[b"profile_uid_", &user_id.to_le_bytes()])
+    }
+
+    pub fn escrow(&self, user_id: u64) -> Pubkey {
+        pda(&[
+            b"escrow_uid_",
+            &user_id.to_le_bytes(),
+            b"_mint_",
+            self.mint.as_ref(),
+        ])
+    }
+
+    pub fn promo_account(&self, user_id: u64) -> Pubkey {
+        pda(&[b"promo_usdc_uid_", &user_id.to_le_bytes()])
+    }
+
+    pub fn promo_grant(&self, user_id: u64, campaign_id: u64) -> Pubkey {
+        pda(&[
+            b"promo_grant_uid_",
+            &user_id.to_le_bytes(),
+            b"_cid_",
+            &campaign_id.to_le_bytes(),
+        ])
+    }
+
+    pub fn event_data(&self, event_id: u64) -> Pubkey {
+        pda(&[b"eid_", &event_id.to_le_bytes()])
+    }
+
+    pub fn event_escrow(&self, event_id: u64) -> Pubkey {
+        pda(&[b"usdc_eid_", &event_id.to_le_bytes()])
+    }
+
+    pub fn order(&self, order_id: u64) -> Pubkey {
+        pda(&[b"oid_", &order_id.to_le_bytes()])
+    }
+
+    pub fn user_event_data(&self, user_id: u64, event_id: u64) -> Pubkey {
+        pda(&[
+            b"uid_",
+            &user_id.to_le_bytes(),
+            b"_eid_",
+            &event_id.to_le_bytes(),
+        ])
+    }
+
+    pub fn event_fee_config(&self, event_id: u64) -> Pubkey {
+        pda(&[b"fee_config_eid_", &event_id.to_le_bytes()])
+    }
+
+    pub fn event_position_limits(&self, event_id: u64) -> Pubkey {
+        pda(&[b"limits_eid_", &event_id.to_le_bytes()])
+    }
+
+    pub fn fee_vault(&self) -> Pubkey {
+        pda(&[b"fees", self.mint.as_ref()])
+    }
+
+    pub fn fee_ledger(&self) -> Pubkey {
+        pda(&[b"fee_ledger", self.mint.as_ref()])
+    }
+
+    pub async fn set_fee_config(&mut self, fees: solana_ctf::FeeConfigParams) {
+        let accounts = solana_ctf::accounts::SetFeeConfig {
+            owner: solana_ctf::OWNER,
+            fee_config: pda(&[b"fee_config"]),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::SetFeeConfig { params: fees };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn set_event_fee_config(&mut self, event_id: u64, fees: solana_ctf::FeeConfigParams) {
+        let accounts = solana_ctf::accounts::SetEventFeeConfig {
+            owner: solana_ctf::OWNER,
+            event_data: self.event_data(event_id),
+            event_fee_config: self.event_fee_config(event_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::SetEventFeeConfig {
+            params: solana_ctf::EventFeeConfigParams { event_id, fees },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn close_event_fee_config(&mut self, event_id: u64) {
+        let accounts = solana_ctf::accounts::CloseEventFeeConfig {
+            owner: solana_ctf::OWNER,
+            event_data: self.event_data(event_id),
+            event_fee_config: self.event_fee_config(event_id),
+            payer: self.context.payer.pubkey(),
+        };
+        let data = solana_ctf::instruction::CloseEventFeeConfig {
+            params: solana_ctf::CloseEventFeeConfigParams { event_id },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn set_event_position_limits(
+        &mut self,
+        event_id: u64,
+        limits: solana_ctf::PositionLimits,
+    ) {
+        let accounts = solana_ctf::accounts::SetEventPositionLimits {
+            owner: solana_ctf::OWNER,
+            event_data: self.event_data(event_id),
+            event_position_limits: self.event_position_limits(event_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::SetEventPositionLimits {
+            params: solana_ctf::EventPositionLimitsParams { event_id, limits },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn close_event_position_limits(&mut self, event_id: u64) {
+        let accounts = solana_ctf::accounts::CloseEventPositionLimits {
+            owner: solana_ctf::OWNER,
+            event_data: self.event_data(event_id),
+            event_position_limits: self.event_position_limits(event_id),
+            payer: self.context.payer.pubkey(),
+        };
+        let data = solana_ctf::instruction::CloseEventPositionLimits {
+            params: solana_ctf::CloseEventPositionLimitsParams { event_id },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    async fn initialize_fee_vault(&mut self) {
+        let accounts = solana_ctf::accounts::InitializeFeeVault {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            treasury: self.treasury,
+            fee_vault: self.fee_vault(),
+            fee_ledger: self.fee_ledger(),
+            delegate: self.fee_vault(),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            rent: SYSVAR_RENT_PUBKEY,
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::InitializeFeeVault {};
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    async fn initialize_config(&mut self) {
+        let accounts = solana_ctf::accounts::InitializeConfig {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            usdc_mint: self.mint,
+            treasury: self.treasury,
+            arka_usdc_wallet: self.arka_usdc_wallet,
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::InitializeConfig {
+            params: solana_ctf::ConfigParams {
+                withdrawal_delay: 7 * 24 * 60 * 60,
+                fee_authority: solana_ctf::OWNER,
+                position_limits: solana_ctf::PositionLimits::default(),
+                max_promo_per_user: 0,
+                collateral_mints: [Pubkey::default(); solana_ctf::Config::MAX_COLLATERALS],
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn initialize_event(&mut self, event_id: u64, promo_policy: solana_ctf::PromoPolicy) {
+        let ix = self.initialize_event_ix(event_id, self.mint, promo_policy);
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    /// Creates another accepted collateral mint, registered in the first free config slot.
+    pub async fn add_collateral(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        self.create_mint(&mint, 0).await;
+        let mut config: solana_ctf::Config = self.account(&self.config()).await;
+        let slot = config
+            .collateral_mints
+            .iter()
+            .position(|mint| *mint == Pubkey::default())
+            .unwrap();
+        config.collateral_mints[slot] = mint.pubkey();
+
+        let accounts = solana_ctf::accounts::UpdateConfig {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            treasury: self.treasury,
+            arka_usdc_wallet: self.arka_usdc_wallet,
+        };
+        let data = solana_ctf::instruction::UpdateConfig {
+            params: solana_ctf::ConfigParams {
+                withdrawal_delay: config.withdrawal_delay,
+                fee_authority: config.fee_authority,
+                position_limits: config.position_limits,
+                max_promo_per_user: config.max_promo_per_user,
+                collateral_mints: config.collateral_mints,
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+        mint.pubkey()
+    }
+
+    pub fn initialize_event_ix(
+        &self,
+        event_id: u64,
+        mint: Pubkey,
+        promo_policy: solana_ctf::PromoPolicy,
+    ) -> Instruction {
+        let accounts = solana_ctf::accounts::InitializeEvent {
+            owner: solana_ctf::OWNER,
+            event_data: self.event_data(event_id),
+            config: self.config(),
+            mint,
+            escrow_account: self.event_escrow(event_id),
+            delegate: self.event_escrow(event_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            rent: SYSVAR_RENT_PUBKEY,
+            token_program: self.token_program,
+            associated_token_program: spl_associated_token_account::id(),
+        };
+        let data = solana_ctf::instruction::InitializeEvent {
+            data: solana_ctf::InitEventParams {
+                event_id,
+                event_total_price: ONE_DOLLAR,
+                promo_policy,
+            },
+        };
+        ix(accounts, data)
+    }
+
+    pub async fn set_event_promo_policy(
+        &mut self,
+        event_id: u64,
+        promo_policy: solana_ctf::PromoPolicy,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::SetEventPromoPolicy {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            event_data: self.event_data(event_id),
+        };
+        let data = solana_ctf::instruction::SetEventPromoPolicy {
+            params: solana_ctf::EventPromoPolicyParams {
+                event_id,
+                promo_policy,
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await
+    }
+
+    /// Creates a user with `balance` in their wallet, all of it approved to the `money`
+    /// delegate, and `promo_balance` granted under campaign 1.
+    pub async fn create_user(
+        &mut self,
+        user_id: u64,
+        balance: u64,
+        promo_balance: u64,
+    ) -> TestUser {
+        let wallet = Keypair::new();
+        self.airdrop(&wallet.pubkey()).await;
+        let token_account = self.create_token_account(&wallet.pubkey()).await;
+        self.mint_to(&token_account, balance).await;
+        self.approve(&wallet, &token_account, u64::MAX).await;
+
+        let accounts = solana_ctf::accounts::InitializeUserAta {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user_id),
+            escrow_account: self.escrow(user_id),
+            promo_account: self.promo_account(user_id),
+            promo_grant: (promo_balance > 0).then(|| self.promo_grant(user_id, 1)),
+            delegate: self.escrow(user_id),
+            promo_delegate: self.promo_account(user_id),
+            arka_usdc_wallet: self.arka_usdc_wallet,
+            arka_delegate: pda(&[b"money"]),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            rent: SYSVAR_RENT_PUBKEY,
+            token_program: self.token_program,
+            associated_token_program: spl_associated_token_account::id(),
+        };
+        let data = solana_ctf::instruction::InitializeUserAta {
+            data: solana_ctf::InitUserAtaParams {
+                user_id,
+                promo_balance,
+                wallet: wallet.pubkey(),
+                kyc_tier: 0,
+                promo_campaign: solana_ctf::PromoCampaign {
+                    campaign_id: 1,
+                    expires_at: i64::MAX,
+                    wagering_requirement: 0,
+                },
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+
+        TestUser {
+            user_id,
+            wallet,
+            token_account,
+        }
+    }
+
+    /// Grants `amount` more promo to `user` under `campaign_id`, expiring `expires_in` seconds
+    /// from now unless the user wagers `wagering_requirement` first.
+    pub async fn grant_promo(
+        &mut self,
+        user: &TestUser,
+        campaign_id: u64,
+        amount: u64,
+        expires_in: i64,
+        wagering_requirement: u64,
+    ) {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        let accounts = solana_ctf::accounts::GrantPromo {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            promo_grant: self.promo_grant(user.user_id, campaign_id),
+            promo_account: self.promo_account(user.user_id),
+            arka_usdc_wallet: self.arka_usdc_wallet,
+            arka_delegate: pda(&[b"money"]),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::GrantPromo {
+            params: solana_ctf::GrantPromoParams {
+                user_id: user.user_id,
+                amount,
+                promo_campaign: solana_ctf::PromoCampaign {
+                    campaign_id,
+                    expires_at: clock.unix_timestamp + expires_in,
+                    wagering_requirement,
+                },
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await.unwrap();
+    }
+
+    pub async fn expire_promo(
+        &mut self,
+        user: &TestUser,
+        campaign_id: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::ExpirePromo {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            promo_grant: self.promo_grant(user.user_id, campaign_id),
+            promo_account: self.promo_account(user.user_id),
+            treasury: self.treasury,
+            promo_delegate: self.promo_account(user.user_id),
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::ExpirePromo {
+            params: solana_ctf::ExpirePromoParams {
+                user_id: user.user_id,
+                campaign_id,
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await
+    }
+
+    pub async fn clawback_promo(
+        &mut self,
+        user: &TestUser,
+        campaign_id: u64,
+        amount: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::ClawbackPromo {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            promo_grant: self.promo_grant(user.user_id, campaign_id),
+            promo_account: self.promo_account(user.user_id),
+            treasury: self.treasury,
+            promo_delegate: self.promo_account(user.user_id),
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::AdminClawbackPromo {
+            params: solana_ctf::ClawbackPromoParams {
+                user_id: user.user_id,
+                campaign_id,
+                amount,
+            },
+        };
+        self.send(&[ix(accounts, data)], &[]).await
+    }
+
+    pub async fn set_status(&mut self, user: &TestUser, status: u8) {
+        let ix = self.update_profile_ix(user, user.wallet.pubkey(), status, false, None);
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    /// An owner update of the profile to `wallet` and `status`, co-signed by the current
+    /// wallet when `wallet_signs` is set.
+    pub fn update_profile_ix(
+        &self,
+        user: &TestUser,
+        wallet: Pubkey,
+        status: u8,
+        wallet_signs: bool,
+        intent_nonce: Option<u64>,
+    ) -> Instruction {
+        let accounts = solana_ctf::accounts::UpdateUserProfile {
+            owner: solana_ctf::OWNER,
+            user_profile: self.user_profile(user.user_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            wallet: wallet_signs.then(|| user.wallet.pubkey()),
+            instructions_sysvar: intent_nonce.map(|_| sysvar::instructions::ID),
+        };
+        let data = solana_ctf::instruction::UpdateUserProfile {
+            params: solana_ctf::UpdateUserProfileParams {
+                user_id: user.user_id,
+                wallet,
+                kyc_tier: 0,
+                status,
+                referrer: None,
+                intent_nonce,
+            },
+        };
+        ix(accounts, data)
+    }
+
+    /// Moves the bank to a later slot with the clock `seconds` ahead. This also gives
+    /// retried transactions a fresh blockhash.
+    pub async fn advance_clock(&mut self, seconds: i64) {
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        self.context.warp_to_slot(clock.slot + 2).unwrap();
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub fn withdrawal_request(&self, user_id: u64) -> Pubkey {
+        pda(&[
+            b"withdrawal_uid_",
+            &user_id.to_le_bytes(),
+            b"_mint_",
+            self.mint.as_ref(),
+        ])
+    }
+
+    fn withdrawal_params(&self, user: &TestUser) -> solana_ctf::WithdrawalParams {
+        solana_ctf::WithdrawalParams {
+            user_id: user.user_id,
+            mint: self.mint,
+        }
+    }
+
+    pub async fn request_withdrawal(
+        &mut self,
+        user: &TestUser,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::RequestWithdrawal {
+            wallet: user.wallet.pubkey(),
+            config: self.config(),
+            user_profile: self.user_profile(user.user_id),
+            withdrawal_request: self.withdrawal_request(user.user_id),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::RequestWithdrawal {
+            params: self.withdrawal_params(user),
+        };
+        self.send(&[ix(accounts, data)], &[&user.wallet]).await
+    }
+
+    pub async fn execute_withdrawal(
+        &mut self,
+        user: &TestUser,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::ExecuteWithdrawal {
+            wallet: user.wallet.pubkey(),
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            withdrawal_request: self.withdrawal_request(user.user_id),
+            user_usdc_token_account: user.token_account,
+            escrow_account: self.escrow(user.user_id),
+            delegate: self.escrow(user.user_id),
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::ExecuteWithdrawal {
+            params: self.withdrawal_params(user),
+        };
+        self.send(&[ix(accounts, data)], &[&user.wallet]).await
+    }
+
+    pub async fn settle_withdrawal(
+        &mut self,
+        user: &TestUser,
+    ) -> std::result::Result<(), BanksClientError> {
+        let accounts = solana_ctf::accounts::SettleWithdrawal {
+            owner: solana_ctf::OWNER,
+            user_profile: self.user_profile(user.user_id),
+            wallet: user.wallet.pubkey(),
+            withdrawal_request: self.withdrawal_request(user.user_id),
+            escrow_account: self.escrow(user.user_id),
+        };
+        let data = solana_ctf::instruction::SettleWithdrawal {
+            params: self.withdrawal_params(user),
+        };
+        self.send(&[ix(accounts, data)], &[]).await
+    }
+
+    /// Cancels an order with the user's own wallet, refunding its wallet and promo parts.
+    /// Closes the user's escrow, and their promo account too when `with_promo` is set.
+    /// Closes the user's escrow, and the promo account along with the `promo_grants` campaigns
+    /// when given.
+    pub async fn close_user_accounts(
+        &mut self,
+        user: &TestUser,
+        promo_grants: Option<&[u64]>,
+    ) -> std::result::Result<(), BanksClientError> {
+        let with_promo = promo_grants.is_some();
+        let accounts = solana_ctf::accounts::CloseUserAccounts {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            escrow_account: self.escrow(user.user_id),
+            user_token_account: user.token_account,
+            withdrawal_request: self.withdrawal_request(user.user_id),
+            promo_account: with_promo.then(|| self.promo_account(user.user_id)),
+            treasury: with_promo.then_some(self.treasury),
+            payer: self.context.payer.pubkey(),
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::CloseUserAccounts {
+            params: solana_ctf::CloseUserAccountsParams {
+                user_id: user.user_id,
+            },
+        };
+        let mut ix = ix(accounts, data);
+        for campaign_id in promo_grants.unwrap_or_default() {
+            ix.accounts.push(AccountMeta::new(
+                self.promo_grant(user.user_id, *campaign_id),
+                false,
+            ));
+        }
+        self.send(&[ix], &[]).await
+    }
+
+    /// Replaces `address` with an account holding `data` as is, owned by `owner` and rent
+    /// exempt for that size only.
+    pub async fn set_raw_account(&mut self, address: &Pubkey, data: &[u8], owner: &Pubkey) {
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let mut account =
+            AccountSharedData::new(rent.minimum_balance(data.len()), data.len(), owner);
+        account.set_data_from_slice(data);
+        self.context.set_account(address, &account);
+    }
+
+    pub fn migrate_event_data_ix(&self, event_id: u64, outstanding_qty: [u64; 2]) -> Instruction {
+        let accounts = solana_ctf::accounts::MigrateEventData {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            event_data: self.event_data(event_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::MigrateEventData {
+            params: solana_ctf::MigrateEventDataParams {
+                event_id,
+                outstanding_qty,
+            },
+        };
+        ix(accounts, data)
+    }
+
+    pub fn migrate_user_event_data_ix(&self, user_id: u64, event_id: u64) -> Instruction {
+        let accounts = solana_ctf::accounts::MigrateUserEventData {
+            owner: solana_ctf::OWNER,
+            user_event_data: self.user_event_data(user_id, event_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        let data = solana_ctf::instruction::MigrateUserEventData {
+            params: solana_ctf::MigrateUserEventDataParams { user_id, event_id },
+        };
+        ix(accounts, data)
+    }
+
+    pub async fn cancel_order(
+        &mut self,
+        user: &TestUser,
+        order_id: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        // Sell orders give their units back to the position
+        let order: solana_ctf::Order = self.account(&self.order(order_id)).await;
+        let user_event_data = (order.kind == solana_ctf::OrderKind::Sell)
+            .then(|| self.user_event_data(user.user_id, order.event_id));
+        let accounts = solana_ctf::accounts::CancelOrder {
+            authority: user.wallet.pubkey(),
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            order: self.order(order_id),
+            user_usdc_token_account: Some(user.token_account),
+            escrow_account: self.escrow(user.user_id),
+            delegate: self.escrow(user.user_id),
+            promo_account: Some(self.promo_account(user.user_id)),
+            user_event_data,
+            token_program: self.token_program,
+        };
+        let data = solana_ctf::instruction::CancelOrder {
+            params: solana_ctf::CancelOrderParams {
+                order_id,
+                user_id: user.user_id,
+            },
+        };
+        self.send(&[ix(accounts, data)], &[&user.wallet]).await
+    }
+
+    /// Releases `amount` from the escrow, `promo_amount` of it back to the promo account,
+    /// against `order_id` when given.
+    pub async fn release(
+        &mut self,
+        user: &TestUser,
+        order_id: Option<u64>,
+        amount: u64,
+        promo_amount: u64,
+        utr_id: &str,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = self.release_ix(user, order_id, amount, promo_amount, utr_id);
+        self.send(&[ix], &[]).await
+    }
+
+    /// A release of `amount` without an order, passing `open_orders` as the user's open orders.
+    pub async fn release_free(
+        &mut self,
+        user: &TestUser,
+        amount: u64,
+        open_orders: &[u64],
+        utr_id: &str,
+    ) -> std::result::Result<(), BanksClientError> {
+        let mut ix = self.release_ix(user, None, amount, 0, utr_id);
+        for order_id in open_orders {
+            ix.accounts
+                .push(AccountMeta::new_readonly(self.order(*order_id), false));
+        }
+        self.send(&[ix], &[]).await
+    }
+
+    pub fn release_ix(
+        &self,
+        user: &TestUser,
+        order_id: Option<u64>,
+        amount: u64,
+        promo_amount: u64,
+        utr_id: &str,
+    ) -> Instruction {
+        let accounts = solana_ctf::accounts::TranferFromUserPda {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            instructions_sysvar: None,
+            user_usdc_token_account: Some(user.token_account),
+            escrow_account: self.escrow(user.user_id),
+            delegate: self.escrow(user.user_id),
+            promo_account: Some(self.promo_account(user.user_id)),
+            release_receipt: pda(&[b"utr_", hash(utr_id.as_bytes()).as_ref()]),
+            order: order_id.map(|order_id| self.order(order_id)),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            rent: SYSVAR_RENT_PUBKEY,
+            token_program: self.token_program,
+            associated_token_program: spl_associated_token_account::id(),
+        };
+        let data = solana_ctf::instruction::TransferFromUserPdaToWallet {
+            data: solana_ctf::TranferFromUserPdaParams {
+                user_id: user.user_id,
+                amount,
+                event_id: 1,
+                order_id: order_id.unwrap_or_default(),
+                utr_id: utr_id.to_string(),
+                promo_amount,
+                intent_nonce: None,
+            },
+        };
+        ix(accounts, data)
+    }
+
+    /// Fills a buy order. The event's own fee config and position limits are passed when
+    /// the event has them.
+    pub async fn buy_ix(
+        &mut self,
+        user: &TestUser,
+        params: solana_ctf::BuyOrderParams,
+    ) -> Instruction {
+        let event_fee_config = self.event_fee_config(params.event_id);
+        let event_position_limits = self.event_position_limits(params.event_id);
+        let accounts = solana_ctf::accounts::BuyOrder {
+            owner: solana_ctf::OWNER,
+            user_arka_event_account: self.user_event_data(user.user_id, params.event_id),
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            fee_config: pda(&[b"fee_config"]),
+            event_fee_config: self
+                .exists(&event_fee_config)
+                .await
+                .then_some(event_fee_config),
+            event_position_limits: self
+                .exists(&event_position_limits)
+                .await
+                .then_some(event_position_limits),
+            order: self.order(params.order_id),
+            fee_vault: self.fee_vault(),
+            fee_ledger: self.fee_ledger(),
+            referrer_token_account: None,
+            user_usdc_token_account: self.escrow(user.user_id),
+            arka_usdc_event_token_account: self.event_escrow(params.event_id),
+            payer: self.context.payer.pubkey(),
+            rent: SYSVAR_RENT_PUBKEY,
+            system_program: system_program::id(),
+            token_program: self.token_program,
+            delegate: self.escrow(user.user_id),
+            event_data: self.event_data(params.event_id),
+        };
+        ix(accounts, solana_ctf::instruction::BuyOrder { params })
+    }
+
+    pub fn place_sell_ix(
+        &self,
+        user: &TestUser,
+        params: solana_ctf::PlaceSellOrderParams,
+    ) -> Instruction {
+        let accounts = solana_ctf::accounts::PlaceSellOrder {
+            owner: solana_ctf::OWNER,
+            user_profile: self.user_profile(user.user_id),
+            instructions_sysvar: params.intent_nonce.map(|_| sysvar::instructions::ID),
+            user_arka_event_account: self.user_event_data(user.user_id, params.event_id),
+            event_data: self.event_data(params.event_id),
+            order: self.order(params.order_id),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+        };
+        ix(accounts, solana_ctf::instruction::PlaceSellOrder { params })
+    }
+
+    /// Fills sell order `params.order_id`, paying the proceeds into the user's escrow.
+    pub async fn sell_ix(
+        &mut self,
+        user: &TestUser,
+        params: solana_ctf::SellOrderParams,
+    ) -> Instruction {
+        let event_fee_config = self.event_fee_config(params.event_id);
+        let accounts = solana_ctf::accounts::SellOrder {
+            owner: solana_ctf::OWNER,
+            user_arka_event_account: self.user_event_data(user.user_id, params.event_id),
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            fee_config: pda(&[b"fee_config"]),
+            event_fee_config: self
+                .exists(&event_fee_config)
+                .await
+                .then_some(event_fee_config),
+            referrer_token_account: None,
+            order: Some(self.order(params.order_id)),
+            user_usdc_token_account: Some(self.escrow(user.user_id)),
+            arka_usdc_event_token_account: self.event_escrow(params.event_id),
+            promo_account: None,
+            fee_vault: self.fee_vault(),
+            fee_ledger: self.fee_ledger(),
+            payer: self.context.payer.pubkey(),
+            rent: SYSVAR_RENT_PUBKEY,
+            system_program: system_program::id(),
+            token_program: self.token_program,
+            delegate: self.event_escrow(params.event_id),
+            event_data: self.event_data(params.event_id),
+        };
+        ix(accounts, solana_ctf::instruction::SellOrder { params })
+    }
+
+    /// Locks funds for a buy order, spending promo first when `use_promo` is set.
+    pub fn lock_ix(
+        &self,
+        user: &TestUser,
+        params: solana_ctf::TranferFromUserWalletParams,
+        use_promo: bool,
+    ) -> Instruction {
+        let accounts = solana_ctf::accounts::TranferFromUserWallet {
+            owner: solana_ctf::OWNER,
+            config: self.config(),
+            mint: self.mint,
+            user_profile: self.user_profile(user.user_id),
+            event_data: self.event_data(params.event_id),
+            instructions_sysvar: params.intent_nonce.map(|_| sysvar::instructions::ID),
+            user_usdc_token_account: Some(user.token_account),
+            escrow_account: self.escrow(user.user_id),
+            promo_account: use_promo.then(|| self.promo_account(user.user_id)),
+            order: self.order(params.order_id),
+            delegate: pda(&[b"money"]),
+            promo_delegate: use_promo.then(|| self.promo_account(user.user_id)),
+            payer: self.context.payer.pubkey(),
+            system_program: system_program::id(),
+            rent: SYSVAR_RENT_PUBKEY,
+            token_program: self.token_program,
+            associated_token_program: spl_associated_token_account::id(),
+        };
+        ix(
+            accounts,
+            solana_ctf::instruction::TransferFromUserWalletToPda { data: params },
+        )
+    }
+}
+
+/// A buy lock of `quantity` units at `price`, paid from the wallet only.
+pub fn lock_params(
+    user_id: u64,
+    event_id: u64,
+    order_id: u64,
+    price: u64,
+    quantity: u64,
+) -> solana_ctf::TranferFromUserWalletParams {
+    solana_ctf::TranferFromUserWalletParams {
+        user_id,
+        amount: price * quantity,
+        event_id,
+        order_id,
+        order_type: solana_ctf::OrderType::Yes,
+        price,
+        quantity,
+        intent_nonce: None,
+        promo_policy: None,
+    }
+}
+
+/// The Ed25519 instruction carrying the user's signature over `intent`.
+pub fn sign_intent(wallet: &Keypair, intent: &solana_ctf::UserIntent) -> Instruction {
+    let dalek_keypair = ed25519_dalek::Keypair::from_bytes(&wallet.to_bytes()).unwrap();
+    solana_sdk::ed25519_instruction::new_ed25519_instruction(&dalek_keypair, &intent.message())
+}
+
+/// A fee schedule charging `commission_bps` to every tier and `trading_fee_bps` to takers.
+pub fn fee_params(commission_bps: u16, trading_fee_bps: u16) -> solana_ctf::FeeConfigParams {
+    solana_ctf::FeeConfigParams {
+        tier_commission_bps: [commission_bps; solana_ctf::FeeConfig::TIERS],
+        maker_fee_bps: 0,
+        taker_fee_bps: trading_fee_bps,
+        referral_share_bps: 0,
+    }
+}
+
+/// A sell order for `quantity` Yes units at no less than `price`, placed without an intent.
+pub fn place_sell_params(
+    user_id: u64,
+    event_id: u64,
+    order_id: u64,
+    price: u64,
+    quantity: u64,
+) -> solana_ctf::PlaceSellOrderParams {
+    solana_ctf::PlaceSellOrderParams {
+        order_id,
+        user_id,
+        event_id,
+        order_type: solana_ctf::OrderType::Yes,
+        price,
+        quantity,
+        intent_nonce: None,
+    }
+}
+
+/// A taker fill of `quantity` units of sell order `order_id` at `price`.
+pub fn sell_params(
+    user_id: u64,
+    event_id: u64,
+    order_id: u64,
+    price: u64,
+    quantity: u64,
+) -> solana_ctf::SellOrderParams {
+    solana_ctf::SellOrderParams {
+        order_type: solana_ctf::OrderType::Yes,
+        order_price: price,
+        event_id,
+        quantity,
+        user_id,
+        selling_price: price,
+        order_id,
+        liquidity: solana_ctf::Liquidity::Taker,
+    }
+}
+
+/// A taker fill of `quantity` units of order `order_id` at `price`.
+pub fn buy_params(
+    user_id: u64,
+    event_id: u64,
+    order_id: u64,
+    price: u64,
+    quantity: u64,
+) -> solana_ctf::BuyOrderParams {
+    solana_ctf::BuyOrderParams {
+        order_type: solana_ctf::OrderType::Yes,
+        order_price: price,
+        event_id,
+        quantity,
+        user_id,
+        order_id,
+        liquidity: solana_ctf::Liquidity::Taker,
+    }
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::fixtures::{empty_position, event, fee_config};
+use common::{assert_error, buy_params, fee_params, lock_params, omit, TestEnv, ONE_DOLLAR};
+use solana_ctf::{
+    BuyOrderError, FeeConfig, FeeConfigParams, FeeLedger, Liquidity, OrderType, PromoPolicy,
+    UserEventData,
+};
+use solana_program_test::tokio;
+
+fn fees(tier_commission_bps: [u16; FeeConfig::TIERS]) -> FeeConfigParams {
+    FeeConfigParams {
+        tier_commission_bps,
+        maker_fee_bps: 0,
+        taker_fee_bps: 0,
+        referral_share_bps: 0,
+    }
+}
+
+#[test]
+fn test_commission_rate_by_tier() {
+    let mut fee_config = fee_config([0; FeeConfig::TIERS]);
+    fee_config.set(&fees([1000, 750, 500, 250])).unwrap();
+
+    assert_eq!(fee_config.commission_bps(0), 1000);
+    assert_eq!(fee_config.commission_bps(2), 500);
+    // Tiers past the schedule pay the last tier's rate
+    assert_eq!(fee_config.commission_bps(9), 250);
+}
+
+#[test]
+fn test_commission_rate_is_bounded() {
+    let mut fee_config = fee_config([1000; FeeConfig::TIERS]);
+
+    assert!(fee_config.set(&fees([1000, 10_001, 500, 250])).is_err());
+    assert_eq!(fee_config.tier_commission_bps, [1000; FeeConfig::TIERS]);
+    assert!(fee_config
+        .set(&fees([FeeConfig::MAX_COMMISSION_BPS; FeeConfig::TIERS]))
+        .is_ok());
+
+    let mut params = fees([1000; FeeConfig::TIERS]);
+    params.referral_share_bps = 10_001;
+    assert!(fee_config.set(&params).is_err());
+}
+
+#[test]
+fn test_commission_on_profit() {
+    assert_eq!(FeeConfig::apply_bps(200000, 1000), 20000);
+    assert_eq!(FeeConfig::apply_bps(333, 25), 0);
+    assert_eq!(FeeConfig::apply_bps(u64::MAX, 10_000), u64::MAX);
+}
+
+#[test]
+fn test_trading_fee_and_referral_share() {
+    let mut fee_config = fee_config([0; FeeConfig::TIERS]);
+    let mut params = fees([1000; FeeConfig::TIERS]);
+    params.maker_fee_bps = 10;
+    params.taker_fee_bps = 30;
+    params.referral_share_bps = 2000;
+    fee_config.set(&params).unwrap();
+
+    let notional = 900000;
+    let maker_fee = FeeConfig::apply_bps(
+        notional,
+        fee_config.trading_fee_bps(Liquidity::Maker) as u64,
+    );
+    let taker_fee = FeeConfig::apply_bps(
+        notional,
+        fee_config.trading_fee_bps(Liquidity::Taker) as u64,
+    );
+    assert_eq!(maker_fee, 900);
+    assert_eq!(taker_fee, 2700);
+
+    // Only users with a referrer share their fees
+    assert_eq!(fee_config.referral_fee(taker_fee, None), 0);
+    assert_eq!(
+        fee_config.referral_fee(taker_fee, Some(Pubkey::new_unique())),
+        540
+    );
+}
+
+#[test]
+fn test_commission_rate_is_weighted_per_lot() {
+    let mut position = empty_position();
+
+    position
+        .add_position(OrderType::Yes, 400000, 3, 200, 0, 0)
+        .unwrap();
+    position
+        .add_position(OrderType::Yes, 600000, 1, 500, 0, 0)
+        .unwrap();
+    position
+        .add_position(OrderType::No, 300000, 2, 1000, 0, 0)
+        .unwrap();
+
+    assert_eq!(position.total_qty, [4, 2]);
+    assert_eq!(position.avg_purchase_price, [450000, 300000]);
+    // 3 units at 2% and 1 unit at 5%
+    assert_eq!(position.commission_bps, [275, 1000]);
+}
+
+#[test]
+fn test_fee_ledger_reconciles_vault_balance() {
+    let mut ledger = FeeLedger {
+        version: FeeLedger::VERSION,
+        mint: Pubkey::new_unique(),
+        treasury: Pubkey::new_unique(),
+        trading_fees: 0,
+        commission: 0,
+        referral_fees: 0,
+        withdrawn: 0,
+    };
+
+    // A buy paying a taker fee, then a profitable sell, both with a referrer
+    ledger.record(2700, 0, 540).unwrap();
+    ledger.record(1500, 20000, 4300).unwrap();
+    assert_eq!(ledger.collected(), 2700 + 1500 + 20000 - 540 - 4300);
+    // A total that would wrap is rejected and leaves the ledger as it was
+    assert!(ledger.record(0, u64::MAX, 0).is_err());
+    assert_eq!(ledger.commission, 20000);
+
+    ledger.withdrawn += 10000;
+    assert_eq!(ledger.balance(), ledger.collected() - 10000);
+}
+
+#[test]
+fn test_statement_totals_per_side() {
+    let mut position = empty_position();
+
+    position
+        .add_position(OrderType::Yes, 400000, 4, 1000, 480, 0)
+        .unwrap();
+    // Sell half at a profit and a quarter at a loss
+    position
+        .reduce_position(OrderType::Yes, 500000, 2, 20000, 300)
+        .unwrap();
+    position
+        .reduce_position(OrderType::Yes, 300000, 1, 0, 90)
+        .unwrap();
+
+    assert_eq!(position.total_qty[0], 1);
+    assert_eq!(position.realized_pnl[0], 200000 - 100000);
+    assert_eq!(position.commission_paid[0], 20000);
+    assert_eq!(position.trading_fees_paid[0], 480 + 300 + 90);
+    assert_eq!(position.volume[0], 1600000 + 1000000 + 300000);
+    assert_eq!(position.trade_count, [3, 0]);
+}
+
+#[test]
+fn test_statement_rejects_overflowing_trades() {
+    let mut position = empty_position();
+
+    assert!(position
+        .add_position(OrderType::Yes, u64::MAX, 2, 0, 0, 0)
+        .is_err());
+    position
+        .add_position(OrderType::Yes, 1, 1, 0, 0, 0)
+        .unwrap();
+    // Proceeds above i64::MAX cannot be booked as pnl
+    assert!(position
+        .reduce_position(OrderType::Yes, u64::MAX, 1, 0, 0)
+        .is_err());
+    // Nor can more units be sold than are held
+    assert!(position
+        .reduce_position(OrderType::Yes, 1, 2, 0, 0)
+        .is_err());
+    assert_eq!(position.total_qty[0], 1);
+}
+
+#[test]
+fn test_event_fee_config_must_be_passed_once_set() {
+    let default = fee_config([100; FeeConfig::TIERS]);
+    let event_override = fee_config([500; FeeConfig::TIERS]);
+    let mut event = event();
+
+    let commission = |fee_config: Option<&FeeConfig>| fee_config.map(|c| c.commission_bps(0));
+    assert_eq!(commission(event.fee_config(None, &default)), Some(100));
+
+    // Once the event has its own schedule, leaving it out can't fall back to the default
+    event.has_fee_config = true;
+    assert_eq!(commission(event.fee_config(None, &default)), None);
+    assert_eq!(
+        commission(event.fee_config(Some(&event_override), &default)),
+        Some(500)
+    );
+}
+
+#[tokio::test]
+async fn test_lot_commission_comes_from_the_event_fee_config() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.set_fee_config(fee_params(100, 0)).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    env.set_event_fee_config(1, fee_params(500, 0)).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+
+    // Leaving the override out must not record the lot at the default rate
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 1)).await;
+    let event_fee_config = env.event_fee_config(1);
+    assert_error(
+        env.send(&[omit(buy.clone(), &event_fee_config)], &[]).await,
+        BuyOrderError::MissingEventFeeConfig,
+    );
+
+    env.send(&[buy], &[]).await.unwrap();
+    let position: UserEventData = env.account(&env.user_event_data(1, 1)).await;
+    assert_eq!(position.commission_bps[0], 500);
+
+    // Without the override the default applies, weighted into the lot by quantity
+    env.close_event_fee_config(1).await;
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 2)).await;
+    env.send(&[buy], &[]).await.unwrap();
+    let position: UserEventData = env.account(&env.user_event_data(1, 1)).await;
+    assert_eq!(position.commission_bps[0], (500 + 2 * 100) / 3);
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::{assert_error, lock_params, sign_intent, TestEnv, TestUser, ONE_DOLLAR};
+use solana_ctf::{
+    verify_ed25519_ix, IntentAction, IntentError, OrderType, PromoPolicy,
+    TranferFromUserWalletParams, UpdateUserProfileError, UserIntent, UserProfile,
+};
+use solana_program_test::tokio;
+use solana_sdk::ed25519_instruction::new_ed25519_instruction;
+use solana_sdk::signature::{Keypair, Signer};
+
+fn buy_intent(user_id: u64, nonce: u64) -> UserIntent {
+    UserIntent {
+        program_id: solana_ctf::ID,
+        user_id,
+        nonce,
+        action: IntentAction::BuyOrder {
+            order_id: 1,
+            event_id: 1,
+            order_type: OrderType::Yes,
+            price: 300000,
+            quantity: 3,
+            amount: 900000,
+        },
+    }
+}
+
+fn sign(wallet: &Keypair, message: &[u8]) -> solana_sdk::instruction::Instruction {
+    let dalek_keypair = ed25519_dalek::Keypair::from_bytes(&wallet.to_bytes()).unwrap();
+    new_ed25519_instruction(&dalek_keypair, message)
+}
+
+#[test]
+fn test_signed_intent_is_accepted() {
+    let wallet = Keypair::new();
+    let message = buy_intent(1, 1).message();
+    let ix = sign(&wallet, &message);
+
+    assert!(verify_ed25519_ix(&ix, &wallet.pubkey(), &message).is_ok());
+}
+
+#[test]
+fn test_intent_signed_by_other_wallet_is_rejected() {
+    let wallet = Keypair::new();
+    let attacker = Keypair::new();
+    let message = buy_intent(1, 1).message();
+    let ix = sign(&attacker, &message);
+
+    assert!(verify_ed25519_ix(&ix, &wallet.pubkey(), &message).is_err());
+}
+
+#[test]
+fn test_intent_for_other_order_is_rejected() {
+    let wallet = Keypair::new();
+    let ix = sign(&wallet, &buy_intent(1, 1).message());
+
+    // Same user and action, but the signature only covers the nonce it was made for
+    let replayed = buy_intent(1, 2).message();
+    assert!(verify_ed25519_ix(&ix, &wallet.pubkey(), &replayed).is_err());
+
+    // Same nonce, different action
+    let withdraw = UserIntent {
+        action: IntentAction::Withdraw {
+            mint: Pubkey::new_unique(),
+            order_id: 1,
+            utr_id: "utr-1".to_string(),
+            amount: 900000,
+            promo_amount: 0,
+        },
+        ..buy_intent(1, 1)
+    }
+    .message();
+    assert!(verify_ed25519_ix(&ix, &wallet.pubkey(), &withdraw).is_err());
+}
+
+#[test]
+fn test_non_ed25519_instruction_is_rejected() {
+    let wallet = Keypair::new();
+    let message = buy_intent(1, 1).message();
+    let mut ix = sign(&wallet, &message);
+    ix.program_id = Pubkey::new_unique();
+
+    assert!(verify_ed25519_ix(&ix, &wallet.pubkey(), &message).is_err());
+}
+
+fn lock_intent(params: &TranferFromUserWalletParams, nonce: u64) -> UserIntent {
+    UserIntent {
+        program_id: solana_ctf::ID,
+        user_id: params.user_id,
+        nonce,
+        action: IntentAction::BuyOrder {
+            order_id: params.order_id,
+            event_id: params.event_id,
+            order_type: params.order_type,
+            price: params.price,
+            quantity: params.quantity,
+            amount: params.amount,
+        },
+    }
+}
+
+async fn start_with_intent_user() -> (TestEnv, TestUser) {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    env.set_status(&user, UserProfile::STATUS_REQUIRES_INTENT)
+        .await;
+    (env, user)
+}
+
+async fn signed_lock(
+    env: &mut TestEnv,
+    user: &TestUser,
+    order_id: u64,
+    nonce: u64,
+) -> std::result::Result<(), solana_program_test::BanksClientError> {
+    let mut params = lock_params(user.user_id, 1, order_id, 300000, 3);
+    params.intent_nonce = Some(nonce);
+    let signature = sign_intent(&user.wallet, &lock_intent(&params, nonce));
+    let lock = env.lock_ix(user, params, false);
+    env.send(&[signature, lock], &[]).await
+}
+
+#[tokio::test]
+async fn test_user_requiring_intent_must_sign_the_lock() {
+    let (mut env, user) = start_with_intent_user().await;
+
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    assert_error(env.send(&[lock], &[]).await, IntentError::IntentRequired);
+
+    signed_lock(&mut env, &user, 1, 1).await.unwrap();
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.intent_nonce, 1);
+    assert!(env.exists(&env.order(1)).await);
+}
+
+#[tokio::test]
+async fn test_used_nonce_cannot_be_replayed() {
+    let (mut env, user) = start_with_intent_user().await;
+    signed_lock(&mut env, &user, 1, 1).await.unwrap();
+
+    // A fresh, correctly signed intent for another order still can't reuse the nonce
+    assert_error(
+        signed_lock(&mut env, &user, 2, 1).await,
+        IntentError::NonceAlreadyUsed,
+    );
+    assert!(!env.exists(&env.order(2)).await);
+
+    signed_lock(&mut env, &user, 2, 5).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.intent_nonce, 5);
+
+    // Nonces only move forward, skipped ones can't be used later either
+    assert_error(
+        signed_lock(&mut env, &user, 3, 4).await,
+        IntentError::NonceAlreadyUsed,
+    );
+}
+
+#[tokio::test]
+async fn test_intent_is_read_from_the_previous_instruction() {
+    let (mut env, user) = start_with_intent_user().await;
+    let mut params = lock_params(1, 1, 1, 300000, 3);
+    params.intent_nonce = Some(1);
+    let intent = lock_intent(&params, 1);
+
+    // No Ed25519 instruction in front of the lock
+    let lock = env.lock_ix(&user, params.clone(), false);
+    assert_error(
+        env.send(std::slice::from_ref(&lock), &[]).await,
+        IntentError::MissingSignature,
+    );
+
+    // Signed by a wallet other than the one on the profile
+    let signature = sign_intent(&Keypair::new(), &intent);
+    assert_error(
+        env.send(&[signature, lock.clone()], &[]).await,
+        IntentError::InvalidSignature,
+    );
+
+    // Signed for a different amount than the one being locked
+    let mut other = params.clone();
+    other.amount += 1;
+    let signature = sign_intent(&user.wallet, &lock_intent(&other, 1));
+    assert_error(
+        env.send(&[signature, lock.clone()], &[]).await,
+        IntentError::InvalidSignature,
+    );
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.intent_nonce, 0);
+}
+
+#[tokio::test]
+async fn test_wallet_change_needs_the_current_wallets_consent() {
+    let (mut env, user) = start_with_intent_user().await;
+    let other = Keypair::new();
+
+    // The owner alone can neither move the wallet nor drop the intent requirement
+    let rebind = env.update_profile_ix(
+        &user,
+        other.pubkey(),
+        UserProfile::STATUS_REQUIRES_INTENT,
+        false,
+        None,
+    );
+    assert_error(
+        env.send(&[rebind], &[]).await,
+        UpdateUserProfileError::WalletConsentRequired,
+    );
+    let drop_intent = env.update_profile_ix(&user, user.wallet.pubkey(), 0, false, None);
+    assert_error(
+        env.send(&[drop_intent], &[]).await,
+        UpdateUserProfileError::WalletConsentRequired,
+    );
+
+    // An intent signed by another wallet is no consent either
+    let intent = UserIntent {
+        program_id: solana_ctf::ID,
+        user_id: 1,
+        nonce: 1,
+        action: IntentAction::UpdateProfile {
+            wallet: user.wallet.pubkey(),
+            status: 0,
+        },
+    };
+    let update = env.update_profile_ix(&user, user.wallet.pubkey(), 0, false, Some(1));
+    let signature = sign_intent(&other, &intent);
+    assert_error(
+        env.send(&[signature, update.clone()], &[]).await,
+        IntentError::InvalidSignature,
+    );
+
+    // The wallet's own intent drops the requirement
+    let signature = sign_intent(&user.wallet, &intent);
+    env.send(&[signature, update], &[]).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.status, 0);
+    assert_eq!(profile.intent_nonce, 1);
+
+    // And co-signing the update moves the wallet
+    let rebind = env.update_profile_ix(&user, other.pubkey(), 0, true, None);
+    env.send(&[rebind], &[&user.wallet]).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.wallet, other.pubkey());
+}
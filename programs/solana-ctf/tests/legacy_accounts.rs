@@ -0,0 +1,125 @@
+mod common;
+
+use anchor_lang::error::ErrorCode;
+use anchor_lang::prelude::Pubkey;
+use common::fixtures::{legacy_event, legacy_position};
+use common::{
+    assert_error, buy_params, lock_params, place_sell_params, sell_params, TestEnv, ONE_DOLLAR,
+};
+use solana_ctf::{EventData, MigrateUserEventDataError, PromoPolicy, UserEventData, UserProfile};
+use solana_program_test::tokio;
+use solana_sdk::instruction::AccountMeta;
+
+// Accounts written in the layouts from before versioning, next to a position bought today
+async fn start_with_legacy_accounts() -> (TestEnv, common::TestUser) {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 3)).await;
+    env.send(&[buy], &[]).await.unwrap();
+
+    let event: EventData = env.account(&env.event_data(1)).await;
+    let legacy = legacy_event(1, 0, false, event.event_total_price);
+    env.set_raw_account(&env.event_data(1), &legacy, &solana_ctf::ID)
+        .await;
+    let legacy = legacy_position([300000, 0], [3, 0], 10);
+    env.set_raw_account(&env.user_event_data(1, 1), &legacy, &solana_ctf::ID)
+        .await;
+    (env, user)
+}
+
+async fn assert_resized_and_rent_exempt(env: &mut TestEnv, address: &Pubkey, len: usize) {
+    let rent = env.context.banks_client.get_rent().await.unwrap();
+    let account = env
+        .context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), 8 + len);
+    assert_eq!(account.lamports, rent.minimum_balance(8 + len));
+}
+
+#[tokio::test]
+async fn test_legacy_accounts_trade_after_migration() {
+    let (mut env, user) = start_with_legacy_accounts().await;
+
+    // The current layout can't read the old accounts
+    let place = env.place_sell_ix(&user, place_sell_params(1, 1, 2, 300000, 3));
+    assert!(env.send(std::slice::from_ref(&place), &[]).await.is_err());
+
+    let migrate_event = env.migrate_event_data_ix(1, [3, 0]);
+    let migrate_position = env.migrate_user_event_data_ix(1, 1);
+    env.send(&[migrate_event, migrate_position.clone()], &[])
+        .await
+        .unwrap();
+
+    // Both were grown to the current size, the payer topping up their rent
+    let event_data = env.event_data(1);
+    assert_resized_and_rent_exempt(&mut env, &event_data, EventData::LEN).await;
+    let user_event_data = env.user_event_data(1, 1);
+    assert_resized_and_rent_exempt(&mut env, &user_event_data, UserEventData::LEN).await;
+
+    let event: EventData = env.account(&event_data).await;
+    assert_eq!(event.version, EventData::VERSION);
+    assert_eq!(event.collateral_mint, env.mint);
+    assert_eq!(event.outstanding_qty, [3, 0]);
+    let position: UserEventData = env.account(&user_event_data).await;
+    assert_eq!(position.total_qty, [3, 0]);
+    assert_eq!(position.commission_bps, [1000, 1000]);
+    assert_eq!(position.cost_basis, [900000, 0]);
+
+    assert_error(
+        env.send(&[migrate_position], &[]).await,
+        MigrateUserEventDataError::AlreadyMigrated,
+    );
+
+    // The migrated position sells like any other
+    env.send(&[place], &[]).await.unwrap();
+    let sell = env.sell_ix(&user, sell_params(1, 1, 2, 300000, 3)).await;
+    env.send(&[sell], &[]).await.unwrap();
+
+    let position: UserEventData = env.account(&user_event_data).await;
+    assert_eq!(position.total_qty, [0, 0]);
+    assert_eq!(position.trade_count, [1, 0]);
+    let event: EventData = env.account(&event_data).await;
+    assert_eq!(event.outstanding_qty, [0, 0]);
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.open_positions, 0);
+    assert_eq!(env.balance(&env.escrow(1)).await, 900000);
+}
+
+#[tokio::test]
+async fn test_migration_checks_owner_and_seeds() {
+    let (mut env, _user) = start_with_legacy_accounts().await;
+    let legacy = legacy_position([300000, 0], [3, 0], 10);
+
+    // An account at the right address but owned by another program
+    let foreign = env.user_event_data(2, 1);
+    env.set_raw_account(&foreign, &legacy, &spl_token::id())
+        .await;
+    assert_error(
+        env.send(&[env.migrate_user_event_data_ix(2, 1)], &[]).await,
+        ErrorCode::ConstraintOwner,
+    );
+
+    // A program account with the legacy layout that is not the user's PDA
+    let stray = Pubkey::new_unique();
+    env.set_raw_account(&stray, &legacy, &solana_ctf::ID).await;
+    let mut migrate = env.migrate_user_event_data_ix(1, 1);
+    migrate.accounts[1] = AccountMeta::new(stray, false);
+    assert_error(env.send(&[migrate], &[]).await, ErrorCode::ConstraintSeeds);
+
+    // Neither was touched
+    let account = env
+        .context
+        .banks_client
+        .get_account(stray)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data, legacy);
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::fixtures::position;
+use solana_ctf::{OrderType, PositionLimits};
+
+#[test]
+fn test_unlimited_by_default() {
+    let limits = PositionLimits::default();
+    let position = position([1_000_000, 0], [u64::MAX as u128, 0]);
+
+    assert!(limits
+        .check(&position, OrderType::Yes, 1_000_000, u64::MAX)
+        .is_ok());
+}
+
+#[test]
+fn test_quantity_limit_is_per_side() {
+    let limits = PositionLimits {
+        max_qty_per_side: 10,
+        max_notional: 0,
+    };
+    let position = position([8, 10], [0, 0]);
+
+    assert!(limits.check(&position, OrderType::Yes, 2, 0).is_ok());
+    assert!(limits.check(&position, OrderType::Yes, 3, 0).is_err());
+    assert!(limits.check(&position, OrderType::No, 1, 0).is_err());
+}
+
+#[test]
+fn test_notional_limit_covers_both_sides() {
+    let limits = PositionLimits {
+        max_qty_per_side: 0,
+        max_notional: 1_000_000,
+    };
+    let position = position([2, 1], [600000, 300000]);
+
+    assert!(limits.check(&position, OrderType::No, 1, 100000).is_ok());
+    assert!(limits.check(&position, OrderType::No, 1, 100001).is_err());
+}
+
+#[test]
+fn test_units_promised_to_sell_orders_are_not_free() {
+    let mut position = position([10, 4], [0; 2]);
+    position.committed_qty = [6, 0];
+
+    assert_eq!(position.free_qty(OrderType::Yes), 4);
+    assert_eq!(position.free_qty(OrderType::No), 4);
+
+    // A fill or cancel gives the units back
+    position.release_committed(OrderType::Yes, 2);
+    assert_eq!(position.free_qty(OrderType::Yes), 6);
+
+    // Settlement ignores commitments, so less may be held than was promised
+    position.total_qty[0] = 1;
+    assert_eq!(position.free_qty(OrderType::Yes), 0);
+    position.release_committed(OrderType::Yes, 10);
+    assert_eq!(position.committed_qty, [0, 0]);
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, AccountSerialize};
+use common::fixtures::{legacy_event, legacy_position};
+use solana_ctf::{EventData, EventOutcome, OrderType, PromoPolicy, UserEventData};
+
+#[test]
+fn test_legacy_account_is_migrated() {
+    let data = legacy_position([300000, 450000], [3, 2], 10);
+    assert_eq!(data.len(), 8 + UserEventData::LEGACY_LEN);
+
+    let migrated = UserEventData::from_legacy(&data).unwrap();
+    assert_eq!(migrated.avg_purchase_price, [300000, 450000]);
+    assert_eq!(migrated.total_qty, [3, 2]);
+    assert_eq!(migrated.commission_bps, [1000, 1000]);
+    assert_eq!(migrated.cost_basis, [900000, 900000]);
+    assert_eq!(migrated.trade_count, [0, 0]);
+
+    // The migrated account round-trips through the current layout
+    let mut buf = Vec::new();
+    migrated.try_serialize(&mut buf).unwrap();
+    assert_eq!(buf.len(), 8 + UserEventData::LEN);
+    let decoded = UserEventData::try_deserialize(&mut buf.as_slice()).unwrap();
+    assert_eq!(decoded.cost_basis, migrated.cost_basis);
+
+    // Migrating twice is rejected
+    assert!(UserEventData::from_legacy(&buf).is_err());
+}
+
+#[test]
+fn test_other_accounts_are_rejected() {
+    let mut data = legacy_position([1, 1], [1, 1], 0);
+    data[0] ^= 0xff;
+    assert!(UserEventData::from_legacy(&data).is_err());
+    assert!(UserEventData::from_legacy(&[]).is_err());
+}
+
+#[test]
+fn test_cost_basis_keeps_average_exact() {
+    let mut position = UserEventData::from_legacy(&legacy_position([0, 0], [0, 0], 0)).unwrap();
+
+    // 1 + 2 + 2 over 3 units truncates to an average of 1
+    position
+        .add_position(OrderType::Yes, 1, 1, 0, 0, 0)
+        .unwrap();
+    position
+        .add_position(OrderType::Yes, 2, 1, 0, 0, 0)
+        .unwrap();
+    position
+        .add_position(OrderType::Yes, 2, 1, 0, 0, 0)
+        .unwrap();
+    assert_eq!(position.avg_purchase_price[0], 1);
+    assert_eq!(position.cost_basis[0], 5);
+
+    // Partial sales take their share and the last unit takes the rest
+    assert_eq!(position.cost_of(OrderType::Yes, 2), 3);
+    position
+        .reduce_position(OrderType::Yes, 2, 2, 0, 0)
+        .unwrap();
+    assert_eq!(position.realized_pnl[0], 1);
+    position
+        .reduce_position(OrderType::Yes, 2, 1, 0, 0)
+        .unwrap();
+    assert_eq!(position.realized_pnl[0], 1);
+    assert_eq!(position.cost_basis[0], 0);
+    assert_eq!(position.total_qty[0], 0);
+}
+
+#[test]
+fn test_legacy_event_is_migrated() {
+    let usdc = Pubkey::new_unique();
+    let data = legacy_event(7, 1, true, 1000000);
+
+    let migrated = EventData::migrate(&data, usdc, [4, 0]).unwrap();
+    assert_eq!(migrated.version, EventData::VERSION);
+    assert_eq!(migrated.event_id, 7);
+    assert_eq!(migrated.outcome, EventOutcome::Yes);
+    assert!(migrated.is_outcome_set);
+    assert_eq!(migrated.event_total_price, 1000000);
+    assert_eq!(migrated.promo_policy, PromoPolicy::default());
+    assert_eq!(migrated.collateral_mint, usdc);
+    assert_eq!(migrated.outstanding_qty, [4, 0]);
+
+    // The migrated event keeps working: it can't be closed until its positions are sold
+    let mut buf = Vec::new();
+    migrated.try_serialize(&mut buf).unwrap();
+    assert!(buf.len() <= 8 + EventData::LEN);
+    let mut decoded = EventData::try_deserialize(&mut buf.as_slice()).unwrap();
+    assert!(decoded.check_closable(0).is_err());
+    decoded.remove_outstanding(OrderType::Yes, 4);
+    assert!(decoded.check_closable(0).is_ok());
+
+    // Migrating twice is rejected
+    buf.resize(8 + EventData::LEN, 0);
+    assert!(EventData::migrate(&buf, usdc, [0, 0]).is_err());
+}
+
+#[test]
+fn test_unknown_versions_are_rejected() {
+    let mut buf = Vec::new();
+    let position = UserEventData::from_legacy(&legacy_position([1, 1], [1, 1], 0)).unwrap();
+    position.try_serialize(&mut buf).unwrap();
+    buf.resize(8 + UserEventData::LEN, 0);
+
+    // The version follows the discriminator
+    assert_eq!(buf[8], UserEventData::VERSION);
+    assert!(UserEventData::migrate(&buf).is_err());
+    buf[8] = UserEventData::VERSION + 1;
+    assert!(UserEventData::migrate(&buf).is_err());
+
+    // Legacy accounts still go through the legacy conversion
+    let migrated = UserEventData::migrate(&legacy_position([300000, 0], [3, 0], 10)).unwrap();
+    assert_eq!(migrated.version, UserEventData::VERSION);
+    assert_eq!(migrated.cost_basis, [900000, 0]);
+}
+
+#[test]
+fn test_event_len_fits_the_largest_promo_policy() {
+    let mut event = EventData::migrate(
+        &legacy_event(1, 0, false, 1000000),
+        Pubkey::new_unique(),
+        [0, 0],
+    )
+    .unwrap();
+    event.promo_policy = PromoPolicy::CappedBps(PromoPolicy::MAX_CAP_BPS);
+
+    let mut buf = Vec::new();
+    event.try_serialize(&mut buf).unwrap();
+    assert_eq!(buf.len(), 8 + EventData::LEN);
+}
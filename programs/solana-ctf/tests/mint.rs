@@ -1,6 +1,9 @@
+#![allow(clippy::too_many_arguments, clippy::assertions_on_constants, dead_code)]
+
 use std::str::FromStr;
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
 use anchor_lang::InstructionData;
 use anchor_spl::token::TokenAccount;
 use anchor_spl::token::ID as OLD_TOKEN_PROGRAM_ID;
@@ -38,11 +41,10 @@ fn load_keypair_from_file(file_path: &str) -> Keypair {
     let keypair_vec: Vec<u8> = serde_json::from_str(&data).unwrap();
 
     // Convert the Vec<u8> into a Keypair
-    let keypair = Keypair::from_bytes(&keypair_vec)
-        .map_err(|_| "Failed to create Keypair from the provided file")
-        .unwrap();
 
-    keypair
+    Keypair::from_bytes(&keypair_vec)
+        .map_err(|_| "Failed to create Keypair from the provided file")
+        .unwrap()
 }
 
 pub struct UsdcMint {
@@ -68,16 +70,19 @@ async fn close_event_account(
     let event_id = event_id.to_le_bytes();
     let (event_data_pda, _) =
         Pubkey::find_program_address(&[b"eid_", event_id.as_ref()], program_id);
+    let (escrow_pda, _) =
+        Pubkey::find_program_address(&[b"usdc_eid_", event_id.as_ref()], program_id);
 
     let event_account = solana_ctf::accounts::CloseEventAccount {
         owner: OWNER,
         event_data: event_data_pda,
+        escrow_account: escrow_pda,
         payer: payer.pubkey(),
     };
     let ix = solana_ctf::instruction::CloseEventData { params: data };
 
     let create_event_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: event_account.to_account_metas(None),
         data: ix.data(),
     };
@@ -87,7 +92,7 @@ async fn close_event_account(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -131,7 +136,7 @@ async fn create_usdc_mint(
         Some(&payer.pubkey()), // Payer for the transaction
     );
 
-    transaction.sign(&[&payer, &mint], recent_blockhash);
+    transaction.sign(&[payer, &mint], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 
@@ -144,7 +149,7 @@ async fn create_usdc_mint(
 async fn get_usdc_account(bank_client: &mut BanksClient, user_usdc_ata: &Pubkey) -> TokenAccount {
     // Fetch the associated token account data
     let ata_account = bank_client
-        .get_account(user_usdc_ata.clone())
+        .get_account(*user_usdc_ata)
         .await
         .expect("failed to get account")
         .expect("ATA not found");
@@ -168,7 +173,7 @@ async fn mint_usdc(
     let mint_to_ix = mint_to(
         &spl_token::id(),                   // Program ID
         &mint.pubkey(),                     // Mint
-        &usdc_ata,                          // User's token account
+        usdc_ata,                           // User's token account
         &usdc_mint.mint_authority.pubkey(), // Mint authority
         &[],                                // No multisig signers
         1_000_000_000,                      // Amount to mint (e.g., 1000 tokens with 9 decimals)
@@ -177,7 +182,7 @@ async fn mint_usdc(
 
     // Create and sign the transaction
     let mut transaction = Transaction::new_with_payer(&[mint_to_ix], Some(&payer.pubkey()));
-    transaction.sign(&[&payer, &usdc_mint.mint_authority], recent_blockhash);
+    transaction.sign(&[payer, &usdc_mint.mint_authority], recent_blockhash);
 
     // Process the transaction
     bank_client.process_transaction(transaction).await.unwrap();
@@ -217,7 +222,7 @@ async fn create_user_and_mint_usdc(
         &[create_token_account_ix, mint_to_ix],
         Some(&payer.pubkey()),
     );
-    transaction.sign(&[&payer, &usdc_mint.mint_authority], recent_blockhash);
+    transaction.sign(&[payer, &usdc_mint.mint_authority], recent_blockhash);
 
     // Process the transaction
     bank_client.process_transaction(transaction).await.unwrap();
@@ -237,26 +242,156 @@ async fn get_approval(
     recent_blockhash: Hash,
 ) {
     let usdc_token_account = &user.user_usdc_ata;
-    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], &program_id);
+    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], program_id);
     let owner = &user.user_key.pubkey();
 
     let ix = approve(
         &spl_token::id(),
-        &usdc_token_account,
+        usdc_token_account,
         &delegate_account,
-        &owner,
+        owner,
         &[],
         amount, // Amount to approve
     )
     .unwrap();
 
     let mut tx = Transaction::new_with_payer(&[ix], Some(&payer.pubkey()));
-    tx.sign(&[&payer, &user.user_key], recent_blockhash);
+    tx.sign(&[payer, &user.user_key], recent_blockhash);
 
     // Process the transaction
     bank_client.process_transaction(tx).await.unwrap();
 }
 
+async fn initialize_config(
+    bank_client: &mut BanksClient,
+    payer: &Keypair,
+    program_id: &Pubkey,
+    recent_blockhash: Hash,
+    usdc_mint: &UsdcMint,
+    treasury: &Pubkey,
+    arka_usdc_wallet: &Pubkey,
+    keypair: &Keypair,
+) {
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
+    let accounts = solana_ctf::accounts::InitializeConfig {
+        owner: OWNER,
+        config: config_pda,
+        usdc_mint: usdc_mint.mint.pubkey(),
+        treasury: *treasury,
+        arka_usdc_wallet: *arka_usdc_wallet,
+        payer: payer.pubkey(),
+        system_program: system_program::id(),
+    };
+    let ix = solana_ctf::instruction::InitializeConfig {
+        params: solana_ctf::ConfigParams {
+            withdrawal_delay: 7 * 24 * 60 * 60,
+            fee_authority: OWNER,
+            position_limits: solana_ctf::PositionLimits::default(),
+            max_promo_per_user: 10000000,
+            collateral_mints: [Pubkey::default(); solana_ctf::Config::MAX_COLLATERALS],
+        },
+    };
+
+    let init_config_ix = Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: ix.data(),
+    };
+
+    let mut transaction = Transaction::new_with_payer(
+        &[init_config_ix],     // Include the instruction
+        Some(&payer.pubkey()), // Specify the fee payer
+    );
+
+    transaction.sign(&[payer, keypair], recent_blockhash);
+
+    bank_client.process_transaction(transaction).await.unwrap();
+}
+
+async fn set_fee_config(
+    bank_client: &mut BanksClient,
+    payer: &Keypair,
+    program_id: &Pubkey,
+    recent_blockhash: Hash,
+    commission_bps: u16,
+    keypair: &Keypair,
+) {
+    let (fee_config_pda, _) = Pubkey::find_program_address(&[b"fee_config"], program_id);
+
+    let accounts = solana_ctf::accounts::SetFeeConfig {
+        owner: OWNER,
+        fee_config: fee_config_pda,
+        payer: payer.pubkey(),
+        system_program: system_program::id(),
+    };
+    let ix = solana_ctf::instruction::SetFeeConfig {
+        params: solana_ctf::FeeConfigParams {
+            tier_commission_bps: [commission_bps; solana_ctf::FeeConfig::TIERS],
+            maker_fee_bps: 10,
+            taker_fee_bps: 30,
+            referral_share_bps: 0,
+        },
+    };
+
+    let fee_config_ix = Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: ix.data(),
+    };
+
+    let mut transaction = Transaction::new_with_payer(&[fee_config_ix], Some(&payer.pubkey()));
+
+    transaction.sign(&[payer, keypair], recent_blockhash);
+
+    bank_client.process_transaction(transaction).await.unwrap();
+}
+
+async fn initialize_fee_vault(
+    bank_client: &mut BanksClient,
+    payer: &Keypair,
+    program_id: &Pubkey,
+    recent_blockhash: Hash,
+    usdc_mint: &UsdcMint,
+    treasury: &Pubkey,
+    keypair: &Keypair,
+) {
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+    let (fee_vault_pda, _) =
+        Pubkey::find_program_address(&[b"fees", usdc_mint.mint.pubkey().as_ref()], program_id);
+    let (fee_ledger_pda, _) = Pubkey::find_program_address(
+        &[b"fee_ledger", usdc_mint.mint.pubkey().as_ref()],
+        program_id,
+    );
+
+    let accounts = solana_ctf::accounts::InitializeFeeVault {
+        owner: OWNER,
+        config: config_pda,
+        mint: usdc_mint.mint.pubkey(),
+        treasury: *treasury,
+        fee_vault: fee_vault_pda,
+        fee_ledger: fee_ledger_pda,
+        delegate: fee_vault_pda,
+        payer: payer.pubkey(),
+        system_program: system_program::id(),
+        rent: SYSVAR_RENT_PUBKEY,
+        token_program: OLD_TOKEN_PROGRAM_ID,
+    };
+    let ix = solana_ctf::instruction::InitializeFeeVault {};
+
+    let fee_vault_ix = Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: ix.data(),
+    };
+
+    let mut transaction = Transaction::new_with_payer(&[fee_vault_ix], Some(&payer.pubkey()));
+
+    transaction.sign(&[payer, keypair], recent_blockhash);
+
+    bank_client.process_transaction(transaction).await.unwrap();
+}
+
 async fn initialize_event(
     bank_client: &mut BanksClient,
     payer: &Keypair,
@@ -270,6 +405,7 @@ async fn initialize_event(
     let data = solana_ctf::InitEventParams {
         event_id,
         event_total_price,
+        promo_policy: solana_ctf::PromoPolicy::PromoFirst,
     };
     let event_id = data.event_id.to_le_bytes();
     let (event_data_pda, _) =
@@ -278,7 +414,10 @@ async fn initialize_event(
     let (escrow_pda, _) =
         Pubkey::find_program_address(&[b"usdc_eid_", event_id.as_ref()], program_id);
 
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
     let event_account = solana_ctf::accounts::InitializeEvent {
+        config: config_pda,
         owner: OWNER,
         event_data: event_data_pda,
         payer: payer.pubkey(),
@@ -286,14 +425,14 @@ async fn initialize_event(
         system_program: system_program::id(),
         token_program: OLD_TOKEN_PROGRAM_ID,
         associated_token_program: spl_associated_token_account::id(),
-        usdc_mint: usdc_mint.mint.pubkey(),
+        mint: usdc_mint.mint.pubkey(),
         escrow_account: escrow_pda,
         delegate: escrow_pda,
     };
     let ix = solana_ctf::instruction::InitializeEvent { data };
 
     let create_event_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: event_account.to_account_metas(None),
         data: ix.data(),
     };
@@ -303,7 +442,7 @@ async fn initialize_event(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -317,43 +456,76 @@ async fn initialize_user(
     usdc_mint: &UsdcMint,
     keypair: &Keypair,
     arka_usdc_ata: &Pubkey,
+    user: &User,
 ) {
     let data = solana_ctf::InitUserAtaParams {
         user_id,
         promo_balance: 2000000,
+        wallet: user.user_key.pubkey(),
+        kyc_tier: 0,
+        promo_campaign: solana_ctf::PromoCampaign {
+            campaign_id: 1,
+            expires_at: i64::MAX,
+            wagering_requirement: 1000000,
+        },
     };
     let user_id = data.user_id.to_le_bytes();
+    let campaign_id = data.promo_campaign.campaign_id.to_le_bytes();
+    let (promo_grant_pda, _) = Pubkey::find_program_address(
+        &[
+            b"promo_grant_uid_",
+            user_id.as_ref(),
+            b"_cid_",
+            campaign_id.as_ref(),
+        ],
+        program_id,
+    );
 
-    let (escrow_pda, _) =
-        Pubkey::find_program_address(&[b"usdc_uid_", user_id.as_ref()], program_id);
+    let (escrow_pda, _) = Pubkey::find_program_address(
+        &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            usdc_mint.mint.pubkey().as_ref(),
+        ],
+        program_id,
+    );
 
     let (promo_pda, _) =
         Pubkey::find_program_address(&[b"promo_usdc_uid_", user_id.as_ref()], program_id);
-    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], &program_id);
+    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], program_id);
 
     println!(
         "arka_usdc_ata: {:?} {:?} {:?}",
         arka_usdc_ata, escrow_pda, promo_pda
     );
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", user_id.as_ref()], program_id);
+
     let event_account = solana_ctf::accounts::InitializeUserAta {
+        config: config_pda,
+        user_profile: user_profile_pda,
         owner: OWNER,
         payer: payer.pubkey(),
         rent: SYSVAR_RENT_PUBKEY,
         system_program: system_program::id(),
         token_program: OLD_TOKEN_PROGRAM_ID,
         associated_token_program: spl_associated_token_account::id(),
-        usdc_mint: usdc_mint.mint.pubkey(),
+        mint: usdc_mint.mint.pubkey(),
         escrow_account: escrow_pda,
         delegate: escrow_pda,
         promo_account: promo_pda,
+        promo_grant: Some(promo_grant_pda),
         promo_delegate: promo_pda,
-        arka_usdc_wallet: arka_usdc_ata.clone(),
+        arka_usdc_wallet: *arka_usdc_ata,
         arka_delegate: delegate_account,
     };
     let ix = solana_ctf::instruction::InitializeUserAta { data };
 
     let create_user_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: event_account.to_account_metas(None),
         data: ix.data(),
     };
@@ -363,7 +535,7 @@ async fn initialize_user(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -384,34 +556,59 @@ async fn transfer_from_user_wallet_to_pda(
         amount,
         event_id: 1,
         order_id: 1,
-        promo_amount: 20000,
+        order_type: solana_ctf::OrderType::Yes,
+        price: 300000,
+        quantity: 3,
+        intent_nonce: None,
+        promo_policy: None,
     };
     let user_id = data.user_id.to_le_bytes();
-
-    let (escrow_pda, _) =
-        Pubkey::find_program_address(&[b"usdc_uid_", user_id.as_ref()], program_id);
+    let (event_data_pda, _) =
+        Pubkey::find_program_address(&[b"eid_", data.event_id.to_le_bytes().as_ref()], program_id);
+
+    let (escrow_pda, _) = Pubkey::find_program_address(
+        &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            usdc_mint.mint.pubkey().as_ref(),
+        ],
+        program_id,
+    );
     let (promo_pda, _) =
         Pubkey::find_program_address(&[b"promo_usdc_uid_", user_id.as_ref()], program_id);
-    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], &program_id);
+    let (delegate_account, _) = Pubkey::find_program_address(&[b"money"], program_id);
+
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", user_id.as_ref()], program_id);
+    let (order_pda, _) =
+        Pubkey::find_program_address(&[b"oid_", data.order_id.to_le_bytes().as_ref()], program_id);
 
     let event_account = solana_ctf::accounts::TranferFromUserWallet {
+        order: order_pda,
+        user_profile: user_profile_pda,
+        event_data: event_data_pda,
+        instructions_sysvar: None,
+        config: config_pda,
         owner: OWNER,
         payer: payer.pubkey(),
         rent: SYSVAR_RENT_PUBKEY,
         system_program: system_program::id(),
         token_program: OLD_TOKEN_PROGRAM_ID,
         associated_token_program: spl_associated_token_account::id(),
-        usdc_mint: usdc_mint.mint.pubkey(),
+        mint: usdc_mint.mint.pubkey(),
         escrow_account: escrow_pda,
         delegate: delegate_account,
-        user_usdc_token_account: Some(user.user_usdc_ata.clone()),
+        user_usdc_token_account: Some(user.user_usdc_ata),
         promo_account: Some(promo_pda),
         promo_delegate: Some(promo_pda),
     };
     let ix = solana_ctf::instruction::TransferFromUserWalletToPda { data };
 
     let create_user_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: event_account.to_account_metas(None),
         data: ix.data(),
     };
@@ -421,7 +618,7 @@ async fn transfer_from_user_wallet_to_pda(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -443,31 +640,55 @@ async fn transfer_from_user_pda_to_wallet(
         order_id: 1,
         utr_id: "test".to_string(),
         promo_amount: 20000,
+        intent_nonce: None,
     };
     let user_id = data.user_id.to_le_bytes();
 
-    let (escrow_pda, _) =
-        Pubkey::find_program_address(&[b"usdc_uid_", user_id.as_ref()], program_id);
+    let (escrow_pda, _) = Pubkey::find_program_address(
+        &[
+            b"escrow_uid_",
+            user_id.as_ref(),
+            b"_mint_",
+            usdc_mint.mint.pubkey().as_ref(),
+        ],
+        program_id,
+    );
     let (promo_pda, _) =
         Pubkey::find_program_address(&[b"promo_usdc_uid_", user_id.as_ref()], program_id);
 
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", user_id.as_ref()], program_id);
+    let (order_pda, _) =
+        Pubkey::find_program_address(&[b"oid_", data.order_id.to_le_bytes().as_ref()], program_id);
+    let (release_receipt_pda, _) = Pubkey::find_program_address(
+        &[b"utr_", hash(data.utr_id.as_bytes()).as_ref()],
+        program_id,
+    );
+
     let event_account = solana_ctf::accounts::TranferFromUserPda {
+        order: Some(order_pda),
+        release_receipt: release_receipt_pda,
+        user_profile: user_profile_pda,
+        instructions_sysvar: None,
+        config: config_pda,
         owner: OWNER,
         payer: payer.pubkey(),
         rent: SYSVAR_RENT_PUBKEY,
         system_program: system_program::id(),
         token_program: OLD_TOKEN_PROGRAM_ID,
         associated_token_program: spl_associated_token_account::id(),
-        usdc_mint: usdc_mint.mint.pubkey(),
+        mint: usdc_mint.mint.pubkey(),
         escrow_account: escrow_pda,
         delegate: escrow_pda,
-        user_usdc_token_account: Some(user.user_usdc_ata.clone()),
+        user_usdc_token_account: Some(user.user_usdc_ata),
         promo_account: Some(promo_pda),
     };
     let ix = solana_ctf::instruction::TransferFromUserPdaToWallet { data };
 
     let create_user_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: event_account.to_account_metas(None),
         data: ix.data(),
     };
@@ -494,6 +715,7 @@ async fn buy_token(
     quantity: u64,
     arka_usdc_ata: &Pubkey,
     keypair: &Keypair,
+    usdc_mint: &UsdcMint,
 ) {
     let data = solana_ctf::BuyOrderParams {
         order_type,
@@ -501,7 +723,8 @@ async fn buy_token(
         event_id,
         quantity,
         user_id,
-        commission: 10,
+        order_id: 1,
+        liquidity: solana_ctf::Liquidity::Taker,
     };
 
     let event_id = data.event_id.to_le_bytes();
@@ -512,19 +735,50 @@ async fn buy_token(
     let eid = data.event_id.to_le_bytes();
     let user_seed = &[b"uid_", uid.as_ref(), b"_eid_", eid.as_ref()];
 
-    let (user_arka_event_account_pda, _) = Pubkey::find_program_address(user_seed, &program_id);
-    let (delegate_account, _) =
-        Pubkey::find_program_address(&[b"usdc_uid_", uid.as_ref()], &program_id);
+    let (user_arka_event_account_pda, _) = Pubkey::find_program_address(user_seed, program_id);
+    let (delegate_account, _) = Pubkey::find_program_address(
+        &[
+            b"escrow_uid_",
+            uid.as_ref(),
+            b"_mint_",
+            usdc_mint.mint.pubkey().as_ref(),
+        ],
+        program_id,
+    );
+
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", uid.as_ref()], program_id);
+    let (order_pda, _) =
+        Pubkey::find_program_address(&[b"oid_", data.order_id.to_le_bytes().as_ref()], program_id);
+    let (fee_config_pda, _) = Pubkey::find_program_address(&[b"fee_config"], program_id);
+    let (fee_vault_pda, _) =
+        Pubkey::find_program_address(&[b"fees", usdc_mint.mint.pubkey().as_ref()], program_id);
+    let (fee_ledger_pda, _) = Pubkey::find_program_address(
+        &[b"fee_ledger", usdc_mint.mint.pubkey().as_ref()],
+        program_id,
+    );
 
     let accounts = solana_ctf::accounts::BuyOrder {
+        config: config_pda,
+        user_profile: user_profile_pda,
+        fee_config: fee_config_pda,
+        event_fee_config: None,
+        event_position_limits: None,
+        order: order_pda,
+        fee_vault: fee_vault_pda,
+        fee_ledger: fee_ledger_pda,
+        referrer_token_account: None,
         owner: OWNER,
         user_arka_event_account: user_arka_event_account_pda,
         user_usdc_token_account: delegate_account,
-        arka_usdc_event_token_account: arka_usdc_ata.clone(),
+        arka_usdc_event_token_account: *arka_usdc_ata,
         payer: payer.pubkey(),
         rent: SYSVAR_RENT_PUBKEY,
         system_program: system_program::id(),
-        old_token_program: OLD_TOKEN_PROGRAM_ID,
+        token_program: OLD_TOKEN_PROGRAM_ID,
+        mint: usdc_mint.mint.pubkey(),
         delegate: delegate_account,
         event_data: event_data_pda,
     };
@@ -532,7 +786,7 @@ async fn buy_token(
     let ix = solana_ctf::instruction::BuyOrder { params: data };
 
     let buy_token_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: accounts.to_account_metas(None),
         data: ix.data(),
     };
@@ -542,7 +796,66 @@ async fn buy_token(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
+
+    bank_client.process_transaction(transaction).await.unwrap();
+}
+
+async fn place_sell_order(
+    bank_client: &mut BanksClient,
+    payer: &Keypair,
+    event_id: u64,
+    program_id: &Pubkey,
+    recent_blockhash: Hash,
+    order_type: solana_ctf::OrderType,
+    user_id: u64,
+    quantity: u64,
+    price: u64,
+    keypair: &Keypair,
+) {
+    let data = solana_ctf::PlaceSellOrderParams {
+        order_id: 2,
+        user_id,
+        event_id,
+        order_type,
+        price,
+        quantity,
+        intent_nonce: None,
+    };
+
+    let uid = data.user_id.to_le_bytes();
+    let eid = data.event_id.to_le_bytes();
+    let (user_arka_event_account_pda, _) =
+        Pubkey::find_program_address(&[b"uid_", uid.as_ref(), b"_eid_", eid.as_ref()], program_id);
+    let (event_data_pda, _) = Pubkey::find_program_address(&[b"eid_", eid.as_ref()], program_id);
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", uid.as_ref()], program_id);
+    let (order_pda, _) =
+        Pubkey::find_program_address(&[b"oid_", data.order_id.to_le_bytes().as_ref()], program_id);
+
+    let accounts = solana_ctf::accounts::PlaceSellOrder {
+        owner: OWNER,
+        user_profile: user_profile_pda,
+        instructions_sysvar: None,
+        user_arka_event_account: user_arka_event_account_pda,
+        event_data: event_data_pda,
+        order: order_pda,
+        payer: payer.pubkey(),
+        system_program: system_program::id(),
+    };
+
+    let ix = solana_ctf::instruction::PlaceSellOrder { params: data };
+
+    let place_sell_order_ix = Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: ix.data(),
+    };
+
+    let mut transaction =
+        Transaction::new_with_payer(&[place_sell_order_ix], Some(&payer.pubkey()));
+
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -558,9 +871,9 @@ async fn sell_token(
     user_id: u64,
     quantity: u64,
     arka_event_usdc_ata: &Pubkey,
-    arka_usdc_ata: &Pubkey,
     selling_price: u64,
     keypair: &Keypair,
+    usdc_mint: &UsdcMint,
 ) {
     let data = solana_ctf::SellOrderParams {
         order_type,
@@ -569,7 +882,8 @@ async fn sell_token(
         quantity,
         user_id,
         selling_price,
-        promo_amount: 20000,
+        order_id: 2,
+        liquidity: solana_ctf::Liquidity::Maker,
     };
 
     let event_id = data.event_id.to_le_bytes();
@@ -580,26 +894,55 @@ async fn sell_token(
     let eid = data.event_id.to_le_bytes();
     let user_seed = &[b"uid_", uid.as_ref(), b"_eid_", eid.as_ref()];
 
-    let (user_arka_event_account_pda, _) = Pubkey::find_program_address(user_seed, &program_id);
+    let (user_arka_event_account_pda, _) = Pubkey::find_program_address(user_seed, program_id);
     let (delegate_account, _) =
-        Pubkey::find_program_address(&[b"usdc_eid_", eid.as_ref()], &program_id);
-
-    let (user_usdc_token_account, _) =
-        Pubkey::find_program_address(&[b"usdc_uid_", uid.as_ref()], &program_id);
+        Pubkey::find_program_address(&[b"usdc_eid_", eid.as_ref()], program_id);
+
+    let (user_usdc_token_account, _) = Pubkey::find_program_address(
+        &[
+            b"escrow_uid_",
+            uid.as_ref(),
+            b"_mint_",
+            usdc_mint.mint.pubkey().as_ref(),
+        ],
+        program_id,
+    );
 
     let (promo_account, _) =
-        Pubkey::find_program_address(&[b"promo_usdc_uid_", uid.as_ref()], &program_id);
+        Pubkey::find_program_address(&[b"promo_usdc_uid_", uid.as_ref()], program_id);
+
+    let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+    let (order_pda, _) =
+        Pubkey::find_program_address(&[b"oid_", data.order_id.to_le_bytes().as_ref()], program_id);
+
+    let (user_profile_pda, _) =
+        Pubkey::find_program_address(&[b"profile_uid_", uid.as_ref()], program_id);
+    let (fee_config_pda, _) = Pubkey::find_program_address(&[b"fee_config"], program_id);
+    let (fee_vault_pda, _) =
+        Pubkey::find_program_address(&[b"fees", usdc_mint.mint.pubkey().as_ref()], program_id);
+    let (fee_ledger_pda, _) = Pubkey::find_program_address(
+        &[b"fee_ledger", usdc_mint.mint.pubkey().as_ref()],
+        program_id,
+    );
 
     let accounts = solana_ctf::accounts::SellOrder {
+        config: config_pda,
+        user_profile: user_profile_pda,
+        fee_config: fee_config_pda,
+        event_fee_config: None,
+        referrer_token_account: None,
+        order: Some(order_pda),
         owner: OWNER,
         user_arka_event_account: user_arka_event_account_pda,
         user_usdc_token_account: Some(user_usdc_token_account),
-        arka_usdc_event_token_account: arka_event_usdc_ata.clone(),
-        arka_usdc_token_account: arka_usdc_ata.clone(),
+        arka_usdc_event_token_account: *arka_event_usdc_ata,
+        fee_vault: fee_vault_pda,
+        fee_ledger: fee_ledger_pda,
         payer: payer.pubkey(),
         rent: SYSVAR_RENT_PUBKEY,
         system_program: system_program::id(),
-        old_token_program: OLD_TOKEN_PROGRAM_ID,
+        token_program: OLD_TOKEN_PROGRAM_ID,
+        mint: usdc_mint.mint.pubkey(),
         delegate: delegate_account,
         event_data: event_data_pda,
         promo_account: Some(promo_account),
@@ -608,7 +951,7 @@ async fn sell_token(
     let ix = solana_ctf::instruction::SellOrder { params: data };
 
     let buy_token_ix = Instruction {
-        program_id: program_id.clone(),
+        program_id: *program_id,
         accounts: accounts.to_account_metas(None),
         data: ix.data(),
     };
@@ -618,7 +961,7 @@ async fn sell_token(
         Some(&payer.pubkey()), // Specify the fee payer
     );
 
-    transaction.sign(&[&payer, keypair], recent_blockhash);
+    transaction.sign(&[payer, keypair], recent_blockhash);
 
     bank_client.process_transaction(transaction).await.unwrap();
 }
@@ -638,11 +981,60 @@ async fn test_program() {
     let user_id: u64 = 1;
     let event_id_bytes = event_id.to_le_bytes();
 
+    let arka_usdc_wallet =
+        create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
+    get_approval(
+        &mut banks_client,
+        &program_id,
+        &payer,
+        &arka_usdc_wallet,
+        9000000,
+        recent_blockhash,
+    )
+    .await;
+    get_usdc_account(&mut banks_client, &arka_usdc_wallet.user_usdc_ata).await;
+
+    let arka_usdc_account =
+        create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
+
+    initialize_config(
+        &mut banks_client,
+        &payer,
+        &program_id,
+        recent_blockhash,
+        &usdc_mint,
+        &arka_usdc_account.user_usdc_ata,
+        &arka_usdc_wallet.user_usdc_ata,
+        &keypair,
+    )
+    .await;
+
+    set_fee_config(
+        &mut banks_client,
+        &payer,
+        &program_id,
+        recent_blockhash,
+        1000,
+        &keypair,
+    )
+    .await;
+
+    initialize_fee_vault(
+        &mut banks_client,
+        &payer,
+        &program_id,
+        recent_blockhash,
+        &usdc_mint,
+        &arka_usdc_account.user_usdc_ata,
+        &keypair,
+    )
+    .await;
+
     initialize_event(
         &mut banks_client,
         &payer,
         event_id,
-        1000_000,
+        1_000_000,
         &program_id,
         recent_blockhash,
         &usdc_mint,
@@ -650,18 +1042,18 @@ async fn test_program() {
     )
     .await;
 
-    let arka_usdc_wallet =
+    let user1 =
         create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
     get_approval(
         &mut banks_client,
         &program_id,
         &payer,
-        &arka_usdc_wallet,
-        9000000,
+        &user1,
+        900000,
         recent_blockhash,
     )
     .await;
-    get_usdc_account(&mut banks_client, &arka_usdc_wallet.user_usdc_ata).await;
+    get_usdc_account(&mut banks_client, &user1.user_usdc_ata).await;
 
     initialize_user(
         &mut banks_client,
@@ -672,6 +1064,7 @@ async fn test_program() {
         &usdc_mint,
         &keypair,
         &arka_usdc_wallet.user_usdc_ata,
+        &user1,
     )
     .await;
 
@@ -693,22 +1086,6 @@ async fn test_program() {
 
     get_usdc_account(&mut banks_client, &arka_event_usdc_account_ata).await;
 
-    let arka_usdc_account =
-        create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
-
-    let user1 =
-        create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
-    get_approval(
-        &mut banks_client,
-        &program_id,
-        &payer,
-        &user1,
-        900000,
-        recent_blockhash,
-    )
-    .await;
-    get_usdc_account(&mut banks_client, &user1.user_usdc_ata).await;
-
     let user2 =
         create_user_and_mint_usdc(&mut banks_client, &usdc_mint, &payer, recent_blockhash).await;
     get_approval(
@@ -727,7 +1104,8 @@ async fn test_program() {
         &payer,
         user_id,
         &user1,
-        300000 * 3,
+        // Notional plus the 30 bps taker fee
+        300000 * 3 + 2700,
         &program_id,
         recent_blockhash,
         &usdc_mint,
@@ -747,12 +1125,27 @@ async fn test_program() {
         3,
         &arka_event_usdc_account_ata,
         &keypair,
+        &usdc_mint,
     )
     .await;
 
     get_usdc_account(&mut banks_client, &user1.user_usdc_ata).await;
     get_usdc_account(&mut banks_client, &arka_event_usdc_account_ata).await;
 
+    place_sell_order(
+        &mut banks_client,
+        &payer,
+        event_id,
+        &program_id,
+        recent_blockhash,
+        solana_ctf::OrderType::Yes,
+        user_id,
+        2,
+        450000,
+        &keypair,
+    )
+    .await;
+
     sell_token(
         &mut banks_client,
         &payer,
@@ -764,9 +1157,9 @@ async fn test_program() {
         user_id,
         2,
         &arka_event_usdc_account_ata,
-        &arka_usdc_account.user_usdc_ata,
         500000,
         &keypair,
+        &usdc_mint,
     )
     .await;
 
@@ -774,10 +1167,7 @@ async fn test_program() {
     get_usdc_account(&mut banks_client, &arka_event_usdc_account_ata).await;
     get_usdc_account(&mut banks_client, &arka_usdc_account.user_usdc_ata).await;
 
-    let balance = banks_client
-        .get_balance(payer.pubkey().clone())
-        .await
-        .unwrap();
+    let balance = banks_client.get_balance(payer.pubkey()).await.unwrap();
 
     println!("Balance before closing {:?}", balance);
 
@@ -791,10 +1181,7 @@ async fn test_program() {
     )
     .await;
 
-    let balance = banks_client
-        .get_balance(payer.pubkey().clone())
-        .await
-        .unwrap();
+    let balance = banks_client.get_balance(payer.pubkey()).await.unwrap();
 
     println!("Balance after closing {:?}", balance);
 
@@ -0,0 +1,250 @@
+mod common;
+
+use common::fixtures::locked_order;
+use common::{
+    assert_error, buy_params, lock_params, omit, place_sell_params, sell_params, TestEnv,
+    ONE_DOLLAR,
+};
+use solana_ctf::{
+    BuyOrderError, Order, OrderKind, OrderStatus, PositionLimits, PositionLimitsError, PromoPolicy,
+    SellOrderError, TranferFromUserPdaError, UserEventData, UserProfile,
+};
+use solana_program_test::tokio;
+
+#[test]
+fn test_unfilled_order_refunds_each_source() {
+    let order = locked_order(700000, 200000);
+
+    assert_eq!(order.remaining_qty(), 3);
+    assert_eq!(order.remaining_locked(), 900000);
+    assert_eq!(order.unfilled_split(), (700000, 200000));
+}
+
+#[test]
+fn test_partial_fill_spends_promo_first() {
+    let mut order = locked_order(700000, 200000);
+    order.record_fill(1, 300000);
+
+    assert!(order.is_open());
+    assert_eq!(order.remaining_qty(), 2);
+    assert_eq!(order.remaining_locked(), 600000);
+    assert_eq!(order.unfilled_split(), (600000, 0));
+}
+
+#[test]
+fn test_fill_below_price_leaves_refundable_leftover() {
+    let mut order = locked_order(900000, 0);
+    order.record_fill(3, 3 * 250000);
+
+    assert_eq!(order.status, OrderStatus::Filled);
+    assert!(!order.is_open());
+    assert_eq!(order.unfilled_split(), (150000, 0));
+
+    order.refunded_amount += 150000;
+    assert_eq!(order.remaining_locked(), 0);
+    assert_eq!(order.unfilled_split(), (0, 0));
+}
+
+#[test]
+fn test_sell_order_fills_without_locked_funds() {
+    let mut order = locked_order(0, 0);
+    order.kind = OrderKind::Sell;
+    order.record_fill(2, 0);
+
+    assert_eq!(order.status, OrderStatus::PartiallyFilled);
+    assert_eq!(order.remaining_qty(), 1);
+    assert_eq!(order.remaining_locked(), 0);
+
+    order.record_fill(1, 0);
+    assert_eq!(order.status, OrderStatus::Filled);
+    assert_eq!(order.unfilled_split(), (0, 0));
+}
+
+#[test]
+fn test_refunds_restore_promo_first() {
+    let mut order = locked_order(700000, 200000);
+    order.record_fill(1, 100000);
+
+    assert_eq!(order.refund_split(50000), Some((0, 50000)));
+    assert_eq!(order.refund_split(300000), Some((200000, 100000)));
+    assert_eq!(order.refund_split(800001), None);
+
+    order.record_refund(0, 50000);
+    assert_eq!(order.status, OrderStatus::Cancelled);
+    assert_eq!(order.unfilled_split(), (700000, 50000));
+
+    order.record_refund(250000, 50000);
+    assert_eq!(order.unfilled_split(), (450000, 0));
+    assert_eq!(order.refund_split(450000), Some((450000, 0)));
+}
+
+#[tokio::test]
+async fn test_cancel_after_promo_release_refunds_only_what_is_left() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::CappedBps(10000)).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 5 * ONE_DOLLAR).await;
+
+    // 5 dollars of promo and 4 from the wallet
+    let mut params = lock_params(1, 1, 1, 300000, 30);
+    params.promo_policy = Some(PromoPolicy::CappedBps(10000));
+    let lock = env.lock_ix(&user, params, true);
+    env.send(&[lock], &[]).await.unwrap();
+
+    env.release(&user, Some(1), 2 * ONE_DOLLAR, 2 * ONE_DOLLAR, "utr-1")
+        .await
+        .unwrap();
+    let order: Order = env.account(&env.order(1)).await;
+    assert_eq!(order.unfilled_split(), (4 * ONE_DOLLAR, 3 * ONE_DOLLAR));
+
+    // Only the promo that was not released already goes back to the promo account
+    env.cancel_order(&user, 1).await.unwrap();
+    assert_eq!(env.balance(&env.escrow(1)).await, 0);
+    assert_eq!(env.balance(&env.promo_account(1)).await, 5 * ONE_DOLLAR);
+    assert_eq!(env.balance(&user.token_account).await, 100 * ONE_DOLLAR);
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.open_orders, 0);
+}
+
+#[tokio::test]
+async fn test_event_position_limits_must_be_passed_once_set() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let limits = PositionLimits {
+        max_qty_per_side: 1,
+        max_notional: 0,
+    };
+    env.set_event_position_limits(1, limits).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+
+    // Leaving the limits out must not skip them
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 2)).await;
+    let event_position_limits = env.event_position_limits(1);
+    assert_error(
+        env.send(&[omit(buy.clone(), &event_position_limits)], &[])
+            .await,
+        BuyOrderError::MissingEventPositionLimits,
+    );
+    assert_error(
+        env.send(&[buy], &[]).await,
+        PositionLimitsError::PositionLimitExceeded,
+    );
+
+    // Once closed only the global limits apply
+    env.close_event_position_limits(1).await;
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 300000, 2)).await;
+    env.send(&[buy], &[]).await.unwrap();
+    let position: UserEventData = env.account(&env.user_event_data(1, 1)).await;
+    assert_eq!(position.total_qty[0], 2);
+}
+
+#[tokio::test]
+async fn test_release_is_bound_to_the_users_own_order() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let alice = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    let bob = env.create_user(2, 100 * ONE_DOLLAR, 0).await;
+
+    let lock = env.lock_ix(&alice, lock_params(1, 1, 1, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+    let lock = env.lock_ix(&bob, lock_params(2, 1, 2, 300000, 3), false);
+    env.send(&[lock], &[]).await.unwrap();
+
+    // Alice's escrow can't be released against Bob's order
+    assert_error(
+        env.release(&alice, Some(2), 900000, 0, "utr-1").await,
+        TranferFromUserPdaError::OrderMismatch,
+    );
+
+    // Bob's order is untouched and can still be cancelled
+    env.cancel_order(&bob, 2).await.unwrap();
+    assert_eq!(env.balance(&bob.token_account).await, 100 * ONE_DOLLAR);
+    let profile: UserProfile = env.account(&env.user_profile(2)).await;
+    assert_eq!(profile.open_orders, 0);
+}
+
+#[tokio::test]
+async fn test_release_without_order_only_pays_out_free_cash() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::CappedBps(10000)).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 5 * ONE_DOLLAR).await;
+
+    // 5 dollars of promo and 4 from the wallet are locked, next to 10 dollars of proceeds
+    let mut params = lock_params(1, 1, 1, 300000, 30);
+    params.promo_policy = Some(PromoPolicy::CappedBps(10000));
+    let lock = env.lock_ix(&user, params, true);
+    env.send(&[lock], &[]).await.unwrap();
+    let escrow = env.escrow(1);
+    env.mint_to(&escrow, 10 * ONE_DOLLAR).await;
+
+    // Promo only goes back against its order
+    assert_error(
+        env.release(&user, None, ONE_DOLLAR, ONE_DOLLAR, "utr-1")
+            .await,
+        TranferFromUserPdaError::PromoRequiresOrder,
+    );
+
+    // The open order must be accounted for, and its funds stay locked
+    assert_error(
+        env.release_free(&user, 10 * ONE_DOLLAR, &[], "utr-2").await,
+        TranferFromUserPdaError::MissingOpenOrders,
+    );
+    assert_error(
+        env.release_free(&user, 10 * ONE_DOLLAR + 1, &[1], "utr-3")
+            .await,
+        TranferFromUserPdaError::ExceedsFreeFunds,
+    );
+    env.release_free(&user, 10 * ONE_DOLLAR, &[1], "utr-4")
+        .await
+        .unwrap();
+    assert_eq!(env.balance(&user.token_account).await, 106 * ONE_DOLLAR);
+
+    // The order can still be cancelled in full
+    env.cancel_order(&user, 1).await.unwrap();
+    assert_eq!(env.balance(&escrow).await, 0);
+    assert_eq!(env.balance(&env.promo_account(1)).await, 5 * ONE_DOLLAR);
+    assert_eq!(env.balance(&user.token_account).await, 110 * ONE_DOLLAR);
+}
+
+#[tokio::test]
+async fn test_sell_paying_cash_needs_the_users_account() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 500000, 10), false);
+    env.send(&[lock], &[]).await.unwrap();
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 500000, 10)).await;
+    env.send(&[buy], &[]).await.unwrap();
+    let place = env.place_sell_ix(&user, place_sell_params(1, 1, 2, 500000, 10));
+    env.send(&[place], &[]).await.unwrap();
+
+    // The proceeds would otherwise stay in the event escrow while the position is gone
+    let sell = env.sell_ix(&user, sell_params(1, 1, 2, 500000, 10)).await;
+    let escrow = env.escrow(1);
+    assert_error(
+        env.send(&[omit(sell.clone(), &escrow)], &[]).await,
+        SellOrderError::MissingUsdcAccount,
+    );
+
+    env.send(&[sell], &[]).await.unwrap();
+    assert_eq!(env.balance(&escrow).await, 5 * ONE_DOLLAR);
+    let position: UserEventData = env.account(&env.user_event_data(1, 1)).await;
+    assert_eq!(position.total_qty[0], 0);
+}
+
+#[tokio::test]
+async fn test_buy_notional_overflow_is_rejected() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    let lock = env.lock_ix(&user, lock_params(1, 1, 1, 500000, 10), false);
+    env.send(&[lock], &[]).await.unwrap();
+
+    let buy = env
+        .buy_ix(&user, buy_params(1, 1, 1, 500000, u64::MAX))
+        .await;
+    assert_error(env.send(&[buy], &[]).await, BuyOrderError::AmountOverflow);
+}
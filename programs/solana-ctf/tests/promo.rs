@@ -0,0 +1,92 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use common::fixtures::{config, empty_position, grant, user_profile};
+use solana_ctf::OrderType;
+
+#[test]
+fn test_wagering_counts_volume_since_grant() {
+    let grant = grant(2000000, 1000000, 5000000);
+
+    // Volume traded before the grant does not count
+    assert!(!grant.wagering_met(5000000));
+    assert!(!grant.wagering_met(5999999));
+    assert!(grant.wagering_met(6000000));
+}
+
+#[test]
+fn test_returns_reduce_remaining_promo() {
+    let mut grant = grant(2000000, 0, 0);
+    assert_eq!(grant.remaining(), 2000000);
+
+    grant.returned += 500000;
+    assert_eq!(grant.remaining(), 1500000);
+}
+
+#[test]
+fn test_unspent_promo_is_split_over_grants() {
+    let grant = grant(2000000, 0, 0);
+
+    // A second grant of 6 is outstanding and half of the 8 granted has been spent
+    assert_eq!(grant.unspent(4000000, 8000000), 1000000);
+    // Nothing spent, the grant gets all of what is left of it
+    assert_eq!(grant.unspent(8000000, 8000000), 2000000);
+    // Grants from before outstanding promo was tracked
+    assert_eq!(grant.unspent(1000000, 0), 1000000);
+    assert_eq!(grant.unspent(3000000, 0), 2000000);
+}
+
+#[test]
+fn test_promo_cap_covers_lifetime_grants() {
+    let mut config = config(Pubkey::new_unique());
+    let profile = user_profile(2000000);
+
+    assert!(config.check_promo_cap(&profile, u64::MAX / 2).is_ok());
+
+    config.max_promo_per_user = 3000000;
+    assert!(config.check_promo_cap(&profile, 1000000).is_ok());
+    assert!(config.check_promo_cap(&profile, 1000001).is_err());
+
+    // A grant that would overflow the lifetime total is rejected, capped or not
+    assert!(config.check_promo_cap(&profile, u64::MAX).is_err());
+    config.max_promo_per_user = 0;
+    assert!(config.check_promo_cap(&profile, u64::MAX).is_err());
+}
+
+#[test]
+fn test_promo_funding_is_tracked_per_side() {
+    let mut position = empty_position();
+    // 3 units at 0.4, 0.6 of it paid with promo
+    position
+        .add_position(OrderType::Yes, 400000, 3, 0, 0, 600000)
+        .unwrap();
+    position
+        .add_position(OrderType::No, 300000, 2, 0, 0, 0)
+        .unwrap();
+
+    assert_eq!(position.promo_principal, [600000, 0]);
+    assert_eq!(position.promo_qty, [1, 0]);
+    assert_eq!(position.promo_share(OrderType::No, 2), (0, 0));
+}
+
+#[test]
+fn test_selling_returns_promo_principal_pro_rata() {
+    let mut position = empty_position();
+    position
+        .add_position(OrderType::Yes, 500000, 4, 0, 0, 1000000)
+        .unwrap();
+
+    assert_eq!(position.promo_share(OrderType::Yes, 1), (250000, 0));
+    position
+        .reduce_position(OrderType::Yes, 900000, 1, 0, 0)
+        .unwrap();
+    assert_eq!(position.promo_principal[0], 750000);
+
+    // The last units sold take whatever promo is left
+    assert_eq!(position.promo_share(OrderType::Yes, 3), (750000, 2));
+    position
+        .reduce_position(OrderType::Yes, 900000, 3, 0, 0)
+        .unwrap();
+    assert_eq!(position.promo_principal[0], 0);
+    assert_eq!(position.promo_qty[0], 0);
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{assert_error, lock_params, TestEnv, ONE_DOLLAR};
+use solana_ctf::{PromoGrant, PromoGrantError, PromoPolicy, UserProfile};
+use solana_program_test::tokio;
+
+#[tokio::test]
+async fn test_expiry_takes_only_the_grants_unspent_share() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::PromoFirst).await;
+    // Campaign 1 never expires, campaign 2 does
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 4 * ONE_DOLLAR).await;
+    env.grant_promo(&user, 2, 4 * ONE_DOLLAR, 100, 100 * ONE_DOLLAR)
+        .await;
+
+    // Half of the pooled promo is spent
+    let mut params = lock_params(1, 1, 1, 400000, 10);
+    params.promo_policy = Some(PromoPolicy::PromoFirst);
+    let lock = env.lock_ix(&user, params, true);
+    env.send(&[lock], &[]).await.unwrap();
+    assert_eq!(env.balance(&env.promo_account(1)).await, 4 * ONE_DOLLAR);
+
+    assert_error(
+        env.expire_promo(&user, 2).await,
+        PromoGrantError::PromoNotExpired,
+    );
+    env.advance_clock(200).await;
+    env.expire_promo(&user, 2).await.unwrap();
+
+    // Campaign 2 held half of what was outstanding, so half of the balance expires
+    assert_eq!(env.balance(&env.promo_account(1)).await, 2 * ONE_DOLLAR);
+    let treasury = env.treasury;
+    assert_eq!(env.balance(&treasury).await, 2 * ONE_DOLLAR);
+    let grant: PromoGrant = env.account(&env.promo_grant(1, 2)).await;
+    assert_eq!(grant.remaining(), 0);
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.promo_outstanding, 4 * ONE_DOLLAR);
+
+    // The settled grant can't be expired into campaign 1's promo again
+    env.expire_promo(&user, 2).await.unwrap();
+    assert_eq!(env.balance(&env.promo_account(1)).await, 2 * ONE_DOLLAR);
+}
+
+#[tokio::test]
+async fn test_clawback_is_capped_at_the_grants_unspent_share() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 6 * ONE_DOLLAR).await;
+    env.grant_promo(&user, 2, 2 * ONE_DOLLAR, 100, 0).await;
+
+    // Campaign 2 holds a quarter of the 8 dollars in the promo account
+    assert_error(
+        env.clawback_promo(&user, 2, 2 * ONE_DOLLAR + 1).await,
+        PromoGrantError::ExceedsPromoGrant,
+    );
+    env.clawback_promo(&user, 2, ONE_DOLLAR).await.unwrap();
+
+    assert_eq!(env.balance(&env.promo_account(1)).await, 7 * ONE_DOLLAR);
+    let grant: PromoGrant = env.account(&env.promo_grant(1, 2)).await;
+    assert_eq!(grant.remaining(), ONE_DOLLAR);
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.promo_outstanding, 7 * ONE_DOLLAR);
+}
+
+#[tokio::test]
+async fn test_top_up_keeps_the_longer_expiry_and_larger_requirement() {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::PromoFirst).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    env.grant_promo(&user, 2, ONE_DOLLAR, 1000, 100 * ONE_DOLLAR)
+        .await;
+    let first: PromoGrant = env.account(&env.promo_grant(1, 2)).await;
+
+    // A shorter, easier campaign topping up the same grant changes neither
+    env.grant_promo(&user, 2, 2 * ONE_DOLLAR, 100, 10 * ONE_DOLLAR)
+        .await;
+    let grant: PromoGrant = env.account(&env.promo_grant(1, 2)).await;
+    assert_eq!(grant.amount, 3 * ONE_DOLLAR);
+    assert_eq!(grant.expires_at, first.expires_at);
+    assert_eq!(grant.wagering_requirement, 100 * ONE_DOLLAR);
+
+    // A longer, harder one extends both
+    env.grant_promo(&user, 2, ONE_DOLLAR, 2000, 200 * ONE_DOLLAR)
+        .await;
+    let grant: PromoGrant = env.account(&env.promo_grant(1, 2)).await;
+    assert_eq!(grant.amount, 4 * ONE_DOLLAR);
+    assert_eq!(grant.expires_at, first.expires_at + 1000);
+    assert_eq!(grant.wagering_requirement, 200 * ONE_DOLLAR);
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.promo_outstanding, 4 * ONE_DOLLAR);
+    assert_eq!(profile.promo_expires_at, grant.expires_at);
+}
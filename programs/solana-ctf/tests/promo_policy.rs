@@ -0,0 +1,94 @@
+use solana_ctf::PromoPolicy;
+
+#[test]
+fn test_promo_first_spends_promo_before_usdc() {
+    let policy = PromoPolicy::PromoFirst;
+
+    assert_eq!(
+        PromoPolicy::split(policy, None, 900000, 200000, 1000000),
+        Some((700000, 200000))
+    );
+    assert_eq!(
+        PromoPolicy::split(policy, None, 900000, 2000000, 0),
+        Some((0, 900000))
+    );
+}
+
+#[test]
+fn test_disallowed_and_capped_limit_promo() {
+    assert_eq!(
+        PromoPolicy::split(PromoPolicy::Disallowed, None, 900000, 2000000, 0),
+        Some((900000, 0))
+    );
+    assert_eq!(
+        PromoPolicy::split(PromoPolicy::CappedBps(2500), None, 900000, 2000000, 0),
+        Some((675000, 225000))
+    );
+    assert!(!PromoPolicy::CappedBps(10001).is_valid());
+}
+
+#[test]
+fn test_cash_first_uses_promo_for_the_shortfall() {
+    let policy = PromoPolicy::CashFirst;
+
+    assert_eq!(
+        PromoPolicy::split(policy, None, 900000, 200000, 1000000),
+        Some((900000, 0))
+    );
+    assert_eq!(
+        PromoPolicy::split(policy, None, 900000, 200000, 800000),
+        Some((800000, 100000))
+    );
+    // Promo can't cover the rest of the order
+    assert_eq!(
+        PromoPolicy::split(policy, None, 900000, 50000, 800000),
+        None
+    );
+}
+
+#[test]
+fn test_order_override_only_restricts_event_policy() {
+    let event_policy = PromoPolicy::CappedBps(5000);
+
+    // A looser override still can't spend more promo than the event allows
+    assert_eq!(
+        PromoPolicy::split(
+            event_policy,
+            Some(PromoPolicy::PromoFirst),
+            900000,
+            2000000,
+            0
+        ),
+        Some((450000, 450000))
+    );
+    assert_eq!(
+        PromoPolicy::split(
+            event_policy,
+            Some(PromoPolicy::Disallowed),
+            900000,
+            2000000,
+            0
+        ),
+        Some((900000, 0))
+    );
+    assert_eq!(
+        PromoPolicy::split(
+            event_policy,
+            Some(PromoPolicy::CashFirst),
+            900000,
+            2000000,
+            600000
+        ),
+        Some((600000, 300000))
+    );
+    assert_eq!(
+        PromoPolicy::split(
+            PromoPolicy::Disallowed,
+            Some(PromoPolicy::CashFirst),
+            900000,
+            2000000,
+            600000
+        ),
+        None
+    );
+}
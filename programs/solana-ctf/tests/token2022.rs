@@ -0,0 +1,162 @@
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_pack::Pack;
+use common::{
+    buy_params, fee_params, lock_params, place_sell_params, sell_params, TestEnv, ONE_DOLLAR,
+};
+use solana_ctf::{amount_after_fee, amount_before_fee, epoch_transfer_fee, Order, PromoPolicy};
+use solana_program_test::tokio;
+use spl_token_2022::extension::{
+    transfer_fee::{TransferFee, TransferFeeConfig},
+    BaseStateWithExtensionsMut, ExtensionType, StateWithExtensionsMut,
+};
+
+fn legacy_mint() -> Vec<u8> {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    let mint = spl_token::state::Mint {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    };
+    spl_token::state::Mint::pack(mint, &mut data).unwrap();
+    data
+}
+
+fn token_2022_mint(transfer_fee: Option<(u16, u64)>) -> Vec<u8> {
+    let extensions: Vec<ExtensionType> = transfer_fee
+        .map(|_| vec![ExtensionType::TransferFeeConfig])
+        .unwrap_or_default();
+    let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(&extensions)
+        .unwrap();
+    let mut data = vec![0; len];
+
+    let mut state =
+        StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data)
+            .unwrap();
+    if let Some((basis_points, maximum_fee)) = transfer_fee {
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        config.newer_transfer_fee.transfer_fee_basis_points = basis_points.into();
+        config.newer_transfer_fee.maximum_fee = maximum_fee.into();
+        config.older_transfer_fee = config.newer_transfer_fee;
+    }
+    state.base = spl_token_2022::state::Mint {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    };
+    state.pack_base();
+    state.init_account_type().unwrap();
+    data
+}
+
+#[test]
+fn test_legacy_mint_charges_no_transfer_fee() {
+    let mint = legacy_mint();
+
+    assert!(epoch_transfer_fee(&mint, 0).unwrap().is_none());
+    assert_eq!(amount_after_fee(&mint, 0, 900000).unwrap(), 900000);
+    assert_eq!(amount_before_fee(&mint, 0, 900000).unwrap(), 900000);
+}
+
+#[test]
+fn test_token_2022_mint_without_fee_extension() {
+    let mint = token_2022_mint(None);
+
+    assert!(epoch_transfer_fee(&mint, 0).unwrap().is_none());
+    assert_eq!(amount_after_fee(&mint, 0, 900000).unwrap(), 900000);
+    assert_eq!(amount_before_fee(&mint, 0, 900000).unwrap(), 900000);
+}
+
+#[test]
+fn test_token_2022_transfer_fee_is_withheld_and_grossed_up() {
+    // 1% fee capped at 5000
+    let mint = token_2022_mint(Some((100, 5000)));
+
+    assert_eq!(amount_after_fee(&mint, 0, 300000).unwrap(), 297000);
+    assert_eq!(amount_before_fee(&mint, 0, 297000).unwrap(), 300000);
+
+    // Sending the grossed up amount delivers exactly what was asked for
+    let gross = amount_before_fee(&mint, 0, 123457).unwrap();
+    assert_eq!(amount_after_fee(&mint, 0, gross).unwrap(), 123457);
+
+    // Above the cap only the maximum fee is withheld
+    assert_eq!(amount_after_fee(&mint, 0, 10_000_000).unwrap(), 9_995_000);
+    assert_eq!(amount_before_fee(&mint, 0, 9_995_000).unwrap(), 10_000_000);
+}
+
+#[test]
+fn test_invalid_mint_data_is_rejected() {
+    assert!(epoch_transfer_fee(&[0; 10], 0).is_err());
+}
+
+// Buys 10 units at 0.5 and sells them at 0.6 with a 1% taker fee, checking that the event escrow
+// and the fee vault receive exact amounts and the user pays every transfer fee
+async fn trade_round_trip(token_program: Pubkey, transfer_fee_bps: u16) {
+    let transfer_fee = TransferFee {
+        transfer_fee_basis_points: transfer_fee_bps.into(),
+        maximum_fee: u64::MAX.into(),
+        ..Default::default()
+    };
+    let after_fee = |amount: u64| transfer_fee.calculate_post_fee_amount(amount).unwrap();
+    let before_fee = |amount: u64| transfer_fee.calculate_pre_fee_amount(amount).unwrap();
+
+    let mut env = TestEnv::start(token_program, transfer_fee_bps).await;
+    env.set_fee_config(fee_params(0, 100)).await;
+    env.initialize_event(1, PromoPolicy::Disallowed).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 0).await;
+    let escrow = env.escrow(1);
+    let event_escrow = env.event_escrow(1);
+    let fee_vault = env.fee_vault();
+
+    // Lock: the order records what arrived in the escrow
+    let mut params = lock_params(1, 1, 1, 500000, 10);
+    params.amount = 6 * ONE_DOLLAR;
+    let lock = env.lock_ix(&user, params, false);
+    env.send(&[lock], &[]).await.unwrap();
+    let order: Order = env.account(&env.order(1)).await;
+    assert_eq!(order.usdc_amount, after_fee(6 * ONE_DOLLAR));
+    assert_eq!(env.balance(&escrow).await, order.usdc_amount);
+
+    // Fill: the event escrow gets the notional and the vault the fee, grossed up out of the lock
+    let buy = env.buy_ix(&user, buy_params(1, 1, 1, 500000, 10)).await;
+    env.send(&[buy], &[]).await.unwrap();
+    assert_eq!(env.balance(&event_escrow).await, 5 * ONE_DOLLAR);
+    assert_eq!(env.balance(&fee_vault).await, 50000);
+    let order: Order = env.account(&env.order(1)).await;
+    assert_eq!(
+        order.filled_amount,
+        before_fee(5 * ONE_DOLLAR) + before_fee(50000)
+    );
+    assert_eq!(env.balance(&escrow).await, order.remaining_locked());
+
+    // Sell: the proceeds come out of the event escrow, the seller pays for the vault's fee
+    env.mint_to(&event_escrow, ONE_DOLLAR).await;
+    let escrow_before = env.balance(&escrow).await;
+    let place = env.place_sell_ix(&user, place_sell_params(1, 1, 2, 600000, 10));
+    env.send(&[place], &[]).await.unwrap();
+    let sell = env.sell_ix(&user, sell_params(1, 1, 2, 600000, 10)).await;
+    env.send(&[sell], &[]).await.unwrap();
+
+    assert_eq!(env.balance(&event_escrow).await, 0);
+    assert_eq!(env.balance(&fee_vault).await, 50000 + 60000);
+    assert_eq!(
+        env.balance(&escrow).await - escrow_before,
+        after_fee(6 * ONE_DOLLAR - before_fee(60000))
+    );
+}
+
+#[tokio::test]
+async fn test_trades_settle_with_spl_token() {
+    trade_round_trip(spl_token::id(), 0).await;
+}
+
+#[tokio::test]
+async fn test_trades_settle_with_token_2022() {
+    trade_round_trip(spl_token_2022::id(), 0).await;
+}
+
+#[tokio::test]
+async fn test_trades_settle_with_token_2022_transfer_fees() {
+    trade_round_trip(spl_token_2022::id(), 50).await;
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use common::{assert_error, lock_params, TestEnv, TestUser, ONE_DOLLAR};
+use solana_ctf::{
+    PromoPolicy, TranferFromUserPdaError, UpdateUserProfileError, UserProfile, WithdrawalError,
+};
+use solana_program_test::tokio;
+use solana_sdk::signature::{Keypair, Signer};
+
+const WITHDRAWAL_DELAY: i64 = 7 * 24 * 60 * 60;
+
+async fn start() -> (TestEnv, TestUser) {
+    let mut env = TestEnv::start(spl_token::id(), 0).await;
+    env.initialize_event(1, PromoPolicy::CappedBps(10000)).await;
+    let user = env.create_user(1, 100 * ONE_DOLLAR, 5 * ONE_DOLLAR).await;
+    (env, user)
+}
+
+#[tokio::test]
+async fn test_withdrawal_waits_for_the_delay() {
+    let (mut env, user) = start().await;
+    let escrow = env.escrow(1);
+    env.mint_to(&escrow, 10 * ONE_DOLLAR).await;
+
+    env.request_withdrawal(&user).await.unwrap();
+    assert_error(
+        env.execute_withdrawal(&user).await,
+        WithdrawalError::WithdrawalLocked,
+    );
+
+    env.advance_clock(WITHDRAWAL_DELAY).await;
+    env.execute_withdrawal(&user).await.unwrap();
+
+    assert_eq!(env.balance(&escrow).await, 0);
+    assert_eq!(env.balance(&user.token_account).await, 110 * ONE_DOLLAR);
+    assert!(!env.exists(&env.withdrawal_request(1)).await);
+}
+
+#[tokio::test]
+async fn test_withdrawal_leaves_open_orders_and_promo_alone() {
+    let (mut env, user) = start().await;
+    let escrow = env.escrow(1);
+
+    // Spends all 5 dollars of promo and 4 from the wallet
+    let mut params = lock_params(1, 1, 1, 300000, 30);
+    params.promo_policy = Some(PromoPolicy::CappedBps(10000));
+    let lock = env.lock_ix(&user, params, true);
+    env.send(&[lock], &[]).await.unwrap();
+    // Proceeds of an earlier sale, free to withdraw
+    env.mint_to(&escrow, 10 * ONE_DOLLAR).await;
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.open_orders, 1);
+
+    env.request_withdrawal(&user).await.unwrap();
+    env.advance_clock(WITHDRAWAL_DELAY).await;
+    assert_error(
+        env.execute_withdrawal(&user).await,
+        WithdrawalError::OrdersStillOpen,
+    );
+
+    env.cancel_order(&user, 1).await.unwrap();
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.open_orders, 0);
+    assert_eq!(env.balance(&env.promo_account(1)).await, 5 * ONE_DOLLAR);
+
+    env.advance_clock(0).await;
+    env.execute_withdrawal(&user).await.unwrap();
+
+    assert_eq!(env.balance(&escrow).await, 0);
+    assert_eq!(env.balance(&user.token_account).await, 110 * ONE_DOLLAR);
+    // Promo is only ever spent on orders
+    assert_eq!(env.balance(&env.promo_account(1)).await, 5 * ONE_DOLLAR);
+}
+
+#[tokio::test]
+async fn test_settle_requires_an_empty_escrow() {
+    let (mut env, user) = start().await;
+    let escrow = env.escrow(1);
+    env.mint_to(&escrow, 10 * ONE_DOLLAR).await;
+
+    env.request_withdrawal(&user).await.unwrap();
+    assert_error(
+        env.settle_withdrawal(&user).await,
+        WithdrawalError::EscrowNotSettled,
+    );
+
+    // Once the escrow has been paid out a new request can be settled right away
+    env.advance_clock(WITHDRAWAL_DELAY).await;
+    env.execute_withdrawal(&user).await.unwrap();
+    env.request_withdrawal(&user).await.unwrap();
+    env.settle_withdrawal(&user).await.unwrap();
+
+    assert!(!env.exists(&env.withdrawal_request(1)).await);
+}
+
+#[tokio::test]
+async fn test_only_the_registered_wallet_can_withdraw() {
+    let (mut env, user) = start().await;
+    let impostor = TestUser {
+        user_id: 1,
+        wallet: Keypair::new(),
+        token_account: user.token_account,
+    };
+    env.airdrop(&impostor.wallet.pubkey()).await;
+
+    assert_error(
+        env.request_withdrawal(&impostor).await,
+        WithdrawalError::WalletMismatch,
+    );
+
+    env.request_withdrawal(&user).await.unwrap();
+    env.advance_clock(WITHDRAWAL_DELAY).await;
+    assert_error(
+        env.execute_withdrawal(&impostor).await,
+        WithdrawalError::WalletMismatch,
+    );
+}
+
+#[tokio::test]
+async fn test_operator_cannot_block_the_exit() {
+    let (mut env, user) = start().await;
+    let escrow = env.escrow(1);
+    let mut params = lock_params(1, 1, 1, 300000, 30);
+    params.promo_policy = Some(PromoPolicy::CappedBps(10000));
+    let lock = env.lock_ix(&user, params, true);
+    env.send(&[lock], &[]).await.unwrap();
+    env.mint_to(&escrow, 10 * ONE_DOLLAR).await;
+    env.request_withdrawal(&user).await.unwrap();
+
+    // The operator can't move the profile to a wallet of its own
+    let operator_wallet = Keypair::new();
+    let rebind = env.update_profile_ix(&user, operator_wallet.pubkey(), 0, false, None);
+    assert_error(
+        env.send(&[rebind], &[]).await,
+        UpdateUserProfileError::WalletConsentRequired,
+    );
+
+    // Nor strand the order by releasing its locked funds without it
+    assert_error(
+        env.release_free(&user, 19 * ONE_DOLLAR, &[1], "utr-1")
+            .await,
+        TranferFromUserPdaError::ExceedsFreeFunds,
+    );
+
+    // The user cancels the order and leaves with everything
+    env.cancel_order(&user, 1).await.unwrap();
+    env.advance_clock(WITHDRAWAL_DELAY).await;
+    env.execute_withdrawal(&user).await.unwrap();
+
+    let profile: UserProfile = env.account(&env.user_profile(1)).await;
+    assert_eq!(profile.wallet, user.wallet.pubkey());
+    assert_eq!(profile.open_orders, 0);
+    assert_eq!(env.balance(&escrow).await, 0);
+    assert_eq!(env.balance(&user.token_account).await, 110 * ONE_DOLLAR);
+    assert_eq!(env.balance(&env.promo_account(1)).await, 5 * ONE_DOLLAR);
+}